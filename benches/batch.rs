@@ -0,0 +1,99 @@
+//! Throughput of one `Client` pinging many loopback addresses over a real ICMP socket,
+//! one system call per message against batches of `sendmmsg` and `recvmmsg`.
+//!
+//! Needs a socket that may send ICMP (root, `CAP_NET_RAW` or `ping_group_range`).
+//!
+//! ```text
+//! cargo bench --bench batch [-- HOSTS [ROUNDS]]
+//! ```
+//!
+//! Batching is only done on Linux, and the CPU time is read with `getrusage`, so there is
+//! nothing to measure outside of Unix.
+#[cfg(unix)]
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use futures::future::join_all;
+#[cfg(unix)]
+use surge_ping::{Client, Config};
+
+#[cfg(unix)]
+const BURST: usize = 64;
+
+#[cfg(unix)]
+fn host(i: u32) -> IpAddr {
+    // all of 127.0.0.0/8 answers on Linux
+    IpAddr::V4(Ipv4Addr::from(0x7f00_0001 + i))
+}
+
+/// User and system CPU time used by the process so far.
+#[cfg(unix)]
+fn cpu_time() -> (Duration, Duration) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    let time = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+    (time(usage.ru_utime), time(usage.ru_stime))
+}
+
+#[cfg(unix)]
+async fn run(batch: usize, hosts: u32, rounds: u16) {
+    let config = Config::builder().batch(batch).build();
+    let client = match Client::new(&config).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("batch {}: cannot open an ICMP socket: {}", batch, e);
+            return;
+        }
+    };
+    let mut pingers = Vec::with_capacity(hosts as usize);
+    for i in 0..hosts {
+        let mut pinger = client.pinger(host(i)).await;
+        pinger.timeout(Duration::from_secs(5));
+        pingers.push(pinger);
+    }
+
+    let mut replies = 0;
+    let start = Instant::now();
+    let (user, system) = cpu_time();
+    for seq in 0..rounds {
+        // Bursts small enough not to overflow the socket receive buffer, which on
+        // loopback also holds our own requests.
+        for burst in pingers.chunks(BURST) {
+            let results = join_all(burst.iter().map(|pinger| pinger.ping(seq))).await;
+            replies += results.iter().filter(|result| result.is_ok()).count();
+        }
+    }
+    let elapsed = start.elapsed();
+    let cpu = cpu_time();
+    println!(
+        "batch {:4}: {}/{} replies in {:.2?}, {:.0} replies/s, cpu {:.2?} user {:.2?} system",
+        batch,
+        replies,
+        hosts as usize * rounds as usize,
+        elapsed,
+        replies as f64 / elapsed.as_secs_f64(),
+        cpu.0 - user,
+        cpu.1 - system,
+    );
+}
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() {
+    let mut args = env::args().skip(1).filter(|arg| arg != "--bench");
+    let hosts: u32 = args.next().map_or(10_000, |arg| arg.parse().unwrap());
+    let rounds: u16 = args.next().map_or(5, |arg| arg.parse().unwrap());
+
+    for batch in [1, 64] {
+        run(batch, hosts, rounds).await;
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    println!("the batch bench only runs on Unix");
+}
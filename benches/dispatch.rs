@@ -0,0 +1,58 @@
+//! Throughput of one `Client` pinging tens of thousands of hosts of a `MockNetwork`.
+//!
+//! ```text
+//! cargo bench --bench dispatch [-- HOSTS [ROUNDS]]
+//! ```
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use surge_ping::{
+    mock::{MockHost, MockNetwork},
+    Client, ICMP,
+};
+
+fn host(i: u32) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i))
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = env::args().skip(1).filter(|arg| arg != "--bench");
+    let hosts: u32 = args.next().map_or(50_000, |arg| arg.parse().unwrap());
+    let rounds: u16 = args.next().map_or(5, |arg| arg.parse().unwrap());
+
+    let network = MockNetwork::new();
+    for i in 0..hosts {
+        network.host(host(i), MockHost::new()).unwrap();
+    }
+    let client = Client::with_transport(network.transport(ICMP::V4));
+
+    let start = Instant::now();
+    let mut pingers = Vec::with_capacity(hosts as usize);
+    for i in 0..hosts {
+        let mut pinger = client.pinger(host(i)).await;
+        pinger.timeout(Duration::from_secs(30));
+        pingers.push(pinger);
+    }
+    println!("{} pingers created in {:.2?}", hosts, start.elapsed());
+
+    for seq in 0..rounds {
+        let start = Instant::now();
+        let results = join_all(pingers.iter().map(|pinger| pinger.ping(seq))).await;
+        let elapsed = start.elapsed();
+        let ok = results.iter().filter(|result| result.is_ok()).count();
+        println!(
+            "round {}: {}/{} replies in {:.2?}, {:.0} replies/s",
+            seq,
+            ok,
+            hosts,
+            elapsed,
+            ok as f64 / elapsed.as_secs_f64()
+        );
+    }
+    println!("{:?}", client.dispatch_stats());
+}
@@ -1,94 +1,11 @@
 use std::time::Duration;
 
+use futures::StreamExt;
 use structopt::StructOpt;
-use surge_ping::{Client, Config, IcmpPacket, ICMP};
-use tokio::time;
-
-#[derive(Default, Debug)]
-struct Answer {
-    host: String,
-    transmitted: usize,
-    received: usize,
-    durations: Vec<Duration>,
-}
-impl Answer {
-    fn new(host: &str) -> Answer {
-        Answer {
-            host: host.to_owned(),
-            transmitted: 0,
-            received: 0,
-            durations: Vec::new(),
-        }
-    }
-
-    fn update(&mut self, dur: Option<Duration>) {
-        match dur {
-            Some(dur) => {
-                self.transmitted += 1;
-                self.received += 1;
-                self.durations.push(dur);
-            }
-            None => self.transmitted += 1,
-        }
-    }
-
-    fn min(&self) -> Option<f64> {
-        let min = self
-            .durations
-            .iter()
-            .min()
-            .map(|dur| dur.as_secs_f64() * 1000f64);
-        min
-    }
-
-    fn max(&self) -> Option<f64> {
-        let max = self
-            .durations
-            .iter()
-            .max()
-            .map(|dur| dur.as_secs_f64() * 1000f64);
-        max
-    }
-
-    fn avg(&self) -> Option<f64> {
-        let sum: Duration = self.durations.iter().sum();
-        let avg = sum
-            .checked_div(self.durations.iter().len() as u32)
-            .map(|dur| dur.as_secs_f64() * 1000f64);
-        avg
-    }
-
-    fn mdev(&self) -> Option<f64> {
-        if let Some(avg) = self.avg() {
-            let tmp_sum = self.durations.iter().fold(0_f64, |acc, x| {
-                acc + x.as_secs_f64() * x.as_secs_f64() * 1000000f64
-            });
-            let tmdev = tmp_sum / self.durations.iter().len() as f64 - avg * avg;
-            Some(tmdev.sqrt())
-        } else {
-            None
-        }
-    }
-
-    fn output(&self) {
-        println!("\n--- {} ping statistics ---", self.host);
-        println!(
-            "{} packets transmitted, {} packets received, {:.2}% packet loss",
-            self.transmitted,
-            self.received,
-            (self.transmitted - self.received) as f64 / self.transmitted as f64 * 100_f64
-        );
-        if self.received > 1 {
-            println!(
-                "round-trip min/avg/max/stddev = {:.3}/{:.3}/{:.3}/{:.3} ms",
-                self.min().unwrap(),
-                self.avg().unwrap(),
-                self.max().unwrap(),
-                self.mdev().unwrap()
-            );
-        }
-    }
-}
+use surge_ping::{
+    Config, DualStackClient, FamilyPreference, IcmpPacket, PayloadPattern, PingEvent, PingPlan,
+    PingStatistics, ResolvePlan,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "surge-ping")]
@@ -123,66 +40,156 @@ struct Opt {
     /// how many packets have been received.
     #[structopt(short = "t", long, default_value = "1")]
     timeout: u64,
+
+    /// Specify a deadline, in seconds, before ping exits regardless of how many packets
+    /// have been sent or received.
+    #[structopt(short = "w", long)]
+    deadline: Option<u64>,
+
+    /// Set the IP Time to Live (or IPv6 hop limit) of the requests.
+    #[structopt(long)]
+    ttl: Option<u8>,
+
+    /// Set the TOS (or IPv6 traffic class) byte of the requests and show the one of the
+    /// replies.
+    #[structopt(short = "Q", long)]
+    tos: Option<u8>,
+
+    /// Tag the requests with this firewall mark for policy routing (Linux only).
+    #[structopt(short = "m", long)]
+    mark: Option<u32>,
+
+    /// Use IPv4 only.
+    #[structopt(short = "4")]
+    ipv4: bool,
+
+    /// Use IPv6 only.
+    #[structopt(short = "6")]
+    ipv6: bool,
+
+    /// Measure round trip times with kernel timestamps (Linux only).
+    #[structopt(long)]
+    kernel_timestamps: bool,
+
+    /// Fill the payload with this pattern: up to 16 bytes of hex digits, `incrementing` or
+    /// `random`.
+    #[structopt(short = "p", long)]
+    pattern: Option<PayloadPattern>,
+
+    /// Check that replies echo the payload byte for byte.
+    #[structopt(long)]
+    verify: bool,
+
+    /// Send and receive up to this many messages per system call (Linux only).
+    #[structopt(long, default_value = "1")]
+    batch: usize,
 }
 
 #[tokio::main]
 async fn main() {
     let opt = Opt::from_args();
 
-    let ip = tokio::net::lookup_host(format!("{}:0", opt.host))
-        .await
-        .expect("host lookup error")
-        .next()
-        .map(|val| val.ip())
-        .unwrap();
-
-    let mut interval = time::interval(Duration::from_millis((opt.interval * 1000f64) as u64));
     let mut config_builder = Config::builder();
     if let Some(interface) = opt.iface {
         config_builder = config_builder.interface(&interface);
     }
 
-    if ip.is_ipv6() {
-        config_builder = config_builder.kind(ICMP::V6);
+    if let Some(mark) = opt.mark {
+        config_builder = config_builder.mark(mark);
     }
+
+    if opt.kernel_timestamps {
+        config_builder = config_builder.kernel_timestamps(true);
+    }
+    config_builder = config_builder.batch(opt.batch);
+
     let config = config_builder.build();
 
-    let client = Client::new(&config).await.unwrap();
-    let mut pinger = client.pinger(ip).await;
-    pinger.timeout(Duration::from_secs(opt.timeout));
+    let family = match (opt.ipv4, opt.ipv6) {
+        (true, _) => FamilyPreference::V4Only,
+        (_, true) => FamilyPreference::V6Only,
+        _ => FamilyPreference::Any,
+    };
+    let client = DualStackClient::new(&config).await.unwrap();
+    let mut pinger = client
+        .pinger_for_host(&opt.host, ResolvePlan::new().family(family))
+        .await
+        .expect("host lookup error");
+    let ip = pinger.address();
+    pinger
+        .size(opt.size)
+        .timeout(Duration::from_secs(opt.timeout));
+    if let Some(ttl) = opt.ttl {
+        pinger.ttl(ttl);
+    }
+    if let Some(tos) = opt.tos {
+        pinger.tos(tos);
+    }
+    if let Some(pattern) = opt.pattern {
+        pinger.pattern(pattern);
+    }
+    pinger.verify_payload(opt.verify);
 
-    let mut answer = Answer::new(&opt.host);
+    let mut stats = PingStatistics::new();
     println!("PING {} ({}): {} data bytes", opt.host, ip, opt.size);
-    for idx in 0..opt.count {
-        interval.tick().await;
-        match pinger.ping(idx).await {
+    let mut plan = PingPlan::new()
+        .interval(Duration::from_millis((opt.interval * 1000f64) as u64))
+        .count(opt.count as usize);
+    if let Some(deadline) = opt.deadline {
+        plan = plan.deadline(Duration::from_secs(deadline));
+    }
+    let pinger = pinger.into_pinger();
+    let mut events = pinger.events(plan);
+    while let Some(event) = events.next().await {
+        stats.record_event(&event);
+        let (result, reordered) = match &event {
+            PingEvent::Outcome {
+                result, reordered, ..
+            } => (result, *reordered),
+            PingEvent::Duplicate { seq, rtt } => {
+                println!("icmp_seq={} time={:0.3?} (DUP!)", seq, rtt);
+                continue;
+            }
+            PingEvent::Late { seq, rtt, .. } => {
+                println!("icmp_seq={} time={:0.3?} (late)", seq, rtt);
+                continue;
+            }
+        };
+        let reordered = if reordered { " (reordered)" } else { "" };
+        let tos = match (opt.tos, result) {
+            (Some(_), Ok((reply, _))) => reply
+                .get_tos()
+                .map(|tos| format!(" tos=0x{:02x}", tos))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        match result {
             Ok((IcmpPacket::V4(reply), dur)) => {
                 println!(
-                    "{} bytes from {}: icmp_seq={} ttl={} time={:0.3?}",
+                    "{} bytes from {}: icmp_seq={} ttl={} time={:0.3?}{}{}",
                     reply.get_size(),
                     reply.get_source(),
                     reply.get_sequence(),
                     reply.get_ttl(),
-                    dur
+                    dur,
+                    tos,
+                    reordered
                 );
-                answer.update(Some(dur));
             }
             Ok((IcmpPacket::V6(reply), dur)) => {
                 println!(
-                    "{} bytes from {}: icmp_seq={} hlim={} time={:0.3?}",
+                    "{} bytes from {}: icmp_seq={} hlim={} time={:0.3?}{}{}",
                     reply.get_size(),
                     reply.get_source(),
                     reply.get_sequence(),
                     reply.get_max_hop_limit(),
-                    dur
+                    dur,
+                    tos,
+                    reordered
                 );
-                answer.update(Some(dur));
-            }
-            Err(e) => {
-                println!("{}", e);
-                answer.update(None);
             }
+            Err(e) => println!("{}", e),
         }
     }
-    answer.output();
+    println!("\n--- {} ping statistics ---\n{}", opt.host, stats);
 }
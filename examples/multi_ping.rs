@@ -1,9 +1,8 @@
 use std::net::IpAddr;
 use std::time::Duration;
 
-use futures::future::join_all;
-use surge_ping::{Client, Config, IcmpPacket, ICMP};
-use tokio::time;
+use futures::{future::join_all, StreamExt};
+use surge_ping::{Config, DualStackClient, IcmpPacket, PingPlan};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -17,17 +16,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "2a02:930::ff76",
         "114.114.114.114",
     ];
-    let client_v4 = Client::new(&Config::default()).await?;
-    let client_v6 = Client::new(&Config::builder().kind(ICMP::V6).build()).await?;
+    let client = DualStackClient::new(&Config::default()).await?;
     let mut tasks = Vec::new();
     for ip in &ips {
         match ip.parse() {
-            Ok(IpAddr::V4(addr)) => {
-                tasks.push(tokio::spawn(ping(client_v4.clone(), IpAddr::V4(addr))))
-            }
-            Ok(IpAddr::V6(addr)) => {
-                tasks.push(tokio::spawn(ping(client_v6.clone(), IpAddr::V6(addr))))
-            }
+            Ok(addr) => tasks.push(tokio::spawn(ping(client.clone(), addr))),
             Err(e) => println!("{} parse to ipaddr error: {}", ip, e),
         }
     }
@@ -36,13 +29,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 // Ping an address 5 times， and print output message（interval 1s）
-async fn ping(client: Client, addr: IpAddr) {
+async fn ping(client: DualStackClient, addr: IpAddr) {
     let mut pinger = client.pinger(addr).await;
     pinger.size(56).timeout(Duration::from_secs(1));
-    let mut interval = time::interval(Duration::from_secs(1));
-    for idx in 0..5 {
-        interval.tick().await;
-        match pinger.ping(idx).await {
+    let mut stream = pinger.stream(PingPlan::new().count(5));
+    while let Some((idx, outcome)) = stream.next().await {
+        match outcome {
             Ok((IcmpPacket::V4(packet), dur)) => println!(
                 "No.{}: {} bytes from {}: icmp_seq={} ttl={} time={:0.2?}",
                 idx,
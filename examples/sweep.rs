@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use structopt::StructOpt;
+use surge_ping::{Config, DualStackClient, HostStatus, IpRange, SweepPlan};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "surge-sweep")]
+struct Opt {
+    /// CIDR blocks, ranges (`start-end`) or addresses to sweep.
+    #[structopt(required = true)]
+    ranges: Vec<IpRange>,
+
+    /// Number of retries per host.
+    #[structopt(short = "r", long, default_value = "1")]
+    retries: usize,
+
+    /// Time (in milliseconds) to wait for each reply.
+    #[structopt(short = "t", long, default_value = "500")]
+    timeout: u64,
+
+    /// Number of hosts probed at the same time.
+    #[structopt(short = "c", long, default_value = "64")]
+    concurrency: usize,
+
+    /// Maximum number of requests per second.
+    #[structopt(long)]
+    rate: Option<f64>,
+
+    /// Maximum number of addresses probed.
+    #[structopt(long, default_value = "131072")]
+    max_hosts: usize,
+
+    /// Show dead hosts as well.
+    #[structopt(short = "u", long)]
+    all: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::from_args();
+
+    let client = DualStackClient::new(&Config::default()).await.unwrap();
+    let mut plan = SweepPlan::new()
+        .retries(opt.retries)
+        .timeout(Duration::from_millis(opt.timeout))
+        .concurrency(opt.concurrency)
+        .max_hosts(opt.max_hosts);
+    if let Some(rate) = opt.rate {
+        plan = plan.rate(rate);
+    }
+    let report = client.sweep(&opt.ranges, plan).await;
+    for host in &report.hosts {
+        match &host.status {
+            HostStatus::Alive { rtt, .. } => println!("{} is alive ({:.2?})", host.addr, rtt),
+            HostStatus::Unreachable { from, error } => {
+                println!("{} is unreachable ({} from {})", host.addr, error, from)
+            }
+            HostStatus::Dead if opt.all => println!("{} is unreachable", host.addr),
+            HostStatus::Failed(error) => println!("{} failed: {}", host.addr, error),
+            HostStatus::Dead => {}
+        }
+    }
+    println!(
+        "\n{} targets, {} alive, {} unreachable",
+        report.hosts.len(),
+        report.alive().count(),
+        report.hosts.len() - report.alive().count()
+    );
+    if report.skipped > 0 {
+        println!("{} addresses skipped, see --max-hosts", report.skipped);
+    }
+}
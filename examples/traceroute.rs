@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use structopt::StructOpt;
+use surge_ping::{Client, Config, TracePlan, TraceStatus, ICMP};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "surge-traceroute")]
+struct Opt {
+    #[structopt(short = "h", long)]
+    host: String,
+
+    /// Set the initial time-to-live used in the first outgoing probe packet.
+    #[structopt(short = "f", long, default_value = "1")]
+    first_ttl: u8,
+
+    /// Set the max number of hops (max time-to-live value) traceroute will probe.
+    #[structopt(short = "m", long, default_value = "30")]
+    max_ttl: u8,
+
+    /// Set the number of probes per each hop.
+    #[structopt(short = "q", long, default_value = "3")]
+    queries: usize,
+
+    /// Set the time (in seconds) to wait for a response to a probe.
+    #[structopt(short = "w", long, default_value = "2")]
+    wait: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::from_args();
+
+    let ip = tokio::net::lookup_host(format!("{}:0", opt.host))
+        .await
+        .expect("host lookup error")
+        .next()
+        .map(|val| val.ip())
+        .unwrap();
+
+    let mut config_builder = Config::builder();
+    if ip.is_ipv6() {
+        config_builder = config_builder.kind(ICMP::V6);
+    }
+    let config = config_builder.build();
+
+    let client = Client::new(&config).await.unwrap();
+    let mut pinger = client.pinger(ip).await;
+    pinger.timeout(Duration::from_secs(opt.wait));
+
+    println!(
+        "traceroute to {} ({}), {} hops max",
+        opt.host, ip, opt.max_ttl
+    );
+    let plan = TracePlan::new()
+        .first_hop(opt.first_ttl)
+        .max_hops(opt.max_ttl)
+        .probes(opt.queries);
+    let trace = pinger.traceroute(plan).await.unwrap();
+    for hop in &trace.hops {
+        println!("{}", hop);
+    }
+    if let TraceStatus::Unreachable { from, error } = trace.status {
+        println!("From {} {}", from, error);
+    }
+}
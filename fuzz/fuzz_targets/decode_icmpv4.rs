@@ -0,0 +1,11 @@
+#![no_main]
+use std::net::Ipv4Addr;
+
+use libfuzzer_sys::fuzz_target;
+use socket2::Type;
+use surge_ping::Icmpv4Packet;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Icmpv4Packet::decode(data);
+    let _ = Icmpv4Packet::decode_with(data, Type::DGRAM, Ipv4Addr::LOCALHOST);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use std::net::Ipv6Addr;
+
+use libfuzzer_sys::fuzz_target;
+use surge_ping::Icmpv6Packet;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Icmpv6Packet::decode(data, Ipv6Addr::LOCALHOST);
+});
@@ -4,61 +4,293 @@ use std::os::unix::io::{FromRawFd, IntoRawFd};
 use std::os::windows::io::{FromRawSocket, IntoRawSocket};
 
 use std::{
-    collections::HashMap,
+    convert::TryInto,
     io,
     net::{IpAddr, SocketAddr},
     sync::Arc,
     time::Instant,
 };
 
-use pnet_packet::{icmp, icmpv6, ipv4, ipv6, Packet};
+use pnet_packet::ipv4;
 use socket2::{Domain, Protocol, Socket, Type};
-use tokio::{
-    net::UdpSocket,
-    sync::{broadcast, mpsc, Mutex},
-    task,
-};
-use tracing::warn;
-use uuid::Uuid;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::collections::VecDeque;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use tokio::{io::Interest, sync::oneshot};
+use tokio::{net::UdpSocket, sync::broadcast, task};
+use tracing::{debug, warn};
 
-use crate::{config::Config, Pinger, ICMP};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use crate::sys;
+use crate::{
+    config::Config,
+    dispatch::{Counters, DispatchStats, Mapping},
+    error::{SocketOptionError, SurgeError},
+    icmp::{icmpv4::Icmpv4Packet, icmpv6::Icmpv6Packet, IcmpPacket},
+    ping::{Cache, Delivery},
+    ratelimit::{RateLimitStats, RateLimiter},
+    resolve::{Clients, FamilyPreference, HostPinger, ResolvePlan},
+    sweep::{self, IpRange, SweepPlan, SweepReport},
+    transport::{RecvMeta, SendMeta, SendOptions, Transport, TransportFuture},
+    Pinger, ICMP,
+};
 
-pub(crate) struct Message {
-    pub when: Instant,
-    pub packet: Vec<u8>,
+struct Message {
+    when: Instant,
+    packet: Vec<u8>,
+    addr: IpAddr,
+    meta: RecvMeta,
 }
 
 impl Message {
-    pub(crate) fn new(when: Instant, packet: Vec<u8>) -> Self {
-        Self { when, packet }
+    fn new(when: Instant, packet: Vec<u8>, meta: RecvMeta) -> Self {
+        Self {
+            when: meta.timestamp.unwrap_or(when),
+            packet,
+            addr: meta.addr.ip(),
+            meta,
+        }
     }
 }
 
 #[derive(Clone)]
 pub(crate) struct AsyncSocket {
     inner: Arc<UdpSocket>,
+    sock_type: Type,
+    kind: ICMP,
+    // Held from sending until the transmit timestamp is read, so concurrent sends do
+    // not take each other's timestamps off the error queue.
+    tx_timestamps: Option<Arc<parking_lot::Mutex<()>>>,
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    batch: Option<Arc<Batch>>,
+    // Held while a message is sent with the TTL or TOS of its `SendOptions` set on the
+    // socket, so no other message goes out with them.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    send_lock: Arc<parking_lot::Mutex<()>>,
+}
+
+/// Room for one received message, the same as the buffer of the receive task.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const SLOT: usize = 2048;
+
+/// The receive buffer of a batching socket, unless the `Config` sets one.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+const BATCH_RECV_BUFFER: usize = 1 << 20;
+
+/// The queues of an `AsyncSocket` that sends with `sendmmsg` and receives with `recvmmsg`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+struct Batch {
+    size: usize,
+    outgoing: parking_lot::Mutex<Outgoing>,
+    received: parking_lot::Mutex<Received>,
+}
+
+/// Requests waiting for the next `sendmmsg`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[derive(Default)]
+struct Outgoing {
+    queued: VecDeque<Queued>,
+    /// Whether a task is on its way to send the queue.
+    flushing: bool,
+}
+
+/// Messages read by the last `recvmmsg`, one per slot of `buf`, that were not handed out
+/// yet.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+struct Received {
+    buf: Vec<u8>,
+    metas: VecDeque<RecvMeta>,
+    next_slot: usize,
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl Batch {
+    fn new(size: usize) -> Self {
+        Batch {
+            size,
+            outgoing: parking_lot::Mutex::new(Outgoing::default()),
+            received: parking_lot::Mutex::new(Received {
+                buf: vec![0; size * SLOT],
+                metas: VecDeque::new(),
+                next_slot: 0,
+            }),
+        }
+    }
+}
+
+/// A request queued for `sendmmsg`, with where to report how sending went.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+struct Queued {
+    buf: Vec<u8>,
+    target: SocketAddr,
+    options: SendOptions,
+    tx: oneshot::Sender<io::Result<SendMeta>>,
 }
 
 impl AsyncSocket {
     pub(crate) fn new(config: &Config) -> io::Result<Self> {
-        let socket = match config.kind {
-            ICMP::V4 => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?,
-            ICMP::V6 => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?,
+        let (domain, proto) = match config.kind {
+            ICMP::V4 => (Domain::IPV4, Some(Protocol::ICMPV4)),
+            ICMP::V6 => (Domain::IPV6, Some(Protocol::ICMPV6)),
+        };
+        let socket = match Socket::new(domain, config.sock_type_hint, proto) {
+            Ok(socket) => socket,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                // RAW needs privileges and DGRAM needs `ping_group_range`, try the other one.
+                let fallback = if config.sock_type_hint == Type::DGRAM {
+                    Type::RAW
+                } else {
+                    Type::DGRAM
+                };
+                Socket::new(domain, fallback, proto).map_err(|_| e)?
+            }
+            Err(e) => return Err(e),
         };
+        let sock_type = socket.r#type()?;
         socket.set_nonblocking(true)?;
-        if let Some(sock_addr) = &config.bind {
-            socket.bind(sock_addr)?;
-        }
+        // The device comes first, so a VRF's routing table is used to check the address
+        // bound below.
         #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
-        if let Some(interface) = &config.interface {
-            socket.bind_device(Some(interface.as_bytes()))?;
+        {
+            let device = match (&config.interface, &config.vrf) {
+                (Some(_), Some(_)) => {
+                    return Err(SocketOptionError::wrap("SO_BINDTODEVICE")(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "`interface` and `vrf` both bind the socket to a device",
+                    )))
+                }
+                (Some(device), None) | (None, Some(device)) => Some(device),
+                (None, None) => None,
+            };
+            if let Some(device) = device {
+                socket
+                    .bind_device(Some(device.as_bytes()))
+                    .map_err(SocketOptionError::wrap("SO_BINDTODEVICE"))?;
+            }
+        }
+        #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+        if config.vrf.is_some() {
+            return Err(SocketOptionError::unsupported("SO_BINDTODEVICE"));
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            use std::os::unix::io::AsRawFd;
+            if let Some(mark) = config.mark {
+                socket
+                    .set_mark(mark)
+                    .map_err(SocketOptionError::wrap("SO_MARK"))?;
+            }
+            if config.freebind {
+                match config.kind {
+                    ICMP::V4 => socket
+                        .set_freebind(true)
+                        .map_err(SocketOptionError::wrap("IP_FREEBIND"))?,
+                    ICMP::V6 => socket
+                        .set_freebind_ipv6(true)
+                        .map_err(SocketOptionError::wrap("IPV6_FREEBIND"))?,
+                }
+            }
+            if config.transparent {
+                match config.kind {
+                    ICMP::V4 => sys::set_transparent_v4(socket.as_raw_fd())?,
+                    ICMP::V6 => sys::set_transparent_v6(socket.as_raw_fd())?,
+                }
+            }
+        }
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        {
+            if config.mark.is_some() {
+                return Err(SocketOptionError::unsupported("SO_MARK"));
+            }
+            if config.freebind {
+                return Err(SocketOptionError::unsupported("IP_FREEBIND"));
+            }
+            if config.transparent {
+                return Err(SocketOptionError::unsupported("IP_TRANSPARENT"));
+            }
+        }
+        if let Some(sock_addr) = &config.bind {
+            socket
+                .bind(sock_addr)
+                .map_err(SocketOptionError::wrap("bind"))?;
         }
         if let Some(ttl) = config.ttl {
-            socket.set_ttl(ttl)?;
+            match config.kind {
+                ICMP::V4 => socket
+                    .set_ttl(ttl)
+                    .map_err(SocketOptionError::wrap("IP_TTL"))?,
+                ICMP::V6 => socket
+                    .set_unicast_hops_v6(ttl)
+                    .map_err(SocketOptionError::wrap("IPV6_UNICAST_HOPS"))?,
+            }
+        }
+        if let Some(tos) = config.tos {
+            match config.kind {
+                ICMP::V4 => socket
+                    .set_tos(tos.into())
+                    .map_err(SocketOptionError::wrap("IP_TOS"))?,
+                #[cfg(any(target_os = "android", target_os = "linux"))]
+                ICMP::V6 => {
+                    use std::os::unix::io::AsRawFd;
+                    sys::set_traffic_class(socket.as_raw_fd(), tos)?;
+                }
+                #[cfg(not(any(target_os = "android", target_os = "linux")))]
+                ICMP::V6 => return Err(SocketOptionError::unsupported("IPV6_TCLASS")),
+            }
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            use std::os::unix::io::AsRawFd;
+            match config.kind {
+                ICMP::V4 => sys::set_recv_tos_v4(socket.as_raw_fd())?,
+                ICMP::V6 => sys::set_recv_pktinfo_v6(socket.as_raw_fd())?,
+            }
+            if config.kind == ICMP::V6 && sock_type == Type::RAW {
+                sys::set_icmpv6_filter(socket.as_raw_fd())?;
+            }
+        }
+        // Only batching sockets get bursts of replies, and batching is Linux only.
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let recv_buffer_size = config
+            .recv_buffer_size
+            .or_else(|| (config.batch > 1).then_some(BATCH_RECV_BUFFER));
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        let recv_buffer_size = config.recv_buffer_size;
+        if let Some(size) = recv_buffer_size {
+            socket
+                .set_recv_buffer_size(size)
+                .map_err(SocketOptionError::wrap("SO_RCVBUF"))?;
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if config.dont_fragment {
+            use std::os::unix::io::AsRawFd;
+            sys::set_dont_fragment(socket.as_raw_fd(), config.kind == ICMP::V6)?;
+        }
+        // Requests would be fragmented, and `Pinger::discover_mtu` report the maximum of
+        // its plan whatever the path.
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        if config.dont_fragment {
+            return Err(SocketOptionError::unsupported(match config.kind {
+                ICMP::V4 => "IP_MTU_DISCOVER",
+                ICMP::V6 => "IPV6_MTU_DISCOVER",
+            }));
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        if config.kernel_timestamps {
+            use std::os::unix::io::AsRawFd;
+            sys::set_timestamps(socket.as_raw_fd())?;
+        }
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        if config.kernel_timestamps {
+            return Err(SocketOptionError::unsupported("SO_TIMESTAMPNS"));
         }
         #[cfg(target_os = "freebsd")]
         if let Some(fib) = config.fib {
-            socket.set_fib(fib)?;
+            socket
+                .set_fib(fib)
+                .map_err(SocketOptionError::wrap("SO_SETFIB"))?;
         }
         #[cfg(windows)]
         let socket = UdpSocket::from_std(unsafe {
@@ -69,15 +301,380 @@ impl AsyncSocket {
             UdpSocket::from_std(unsafe { std::net::UdpSocket::from_raw_fd(socket.into_raw_fd()) })?;
         Ok(Self {
             inner: Arc::new(socket),
+            sock_type,
+            kind: config.kind,
+            tx_timestamps: config
+                .kernel_timestamps
+                .then(|| Arc::new(parking_lot::Mutex::new(()))),
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            batch: (config.batch > 1).then(|| Arc::new(Batch::new(config.batch))),
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            send_lock: Arc::default(),
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+impl AsyncSocket {
+    /// Send `buf` with the TTL and TOS of `options` set on the socket for the time of the
+    /// call, restoring the previous ones after. The caller holds `send_lock`.
+    fn send_with_socket_options(
+        &self,
+        buf: &[u8],
+        target: &SocketAddr,
+        options: SendOptions,
+    ) -> io::Result<usize> {
+        let socket = socket2::SockRef::from(&*self.inner);
+        let hop_limit = match options.hop_limit {
+            Some(hop_limit) => Some(self.swap_hop_limit(&socket, hop_limit.into())?),
+            None => None,
+        };
+        let tos = match options.tos.map(|tos| self.swap_tos(&socket, tos.into())) {
+            Some(Err(e)) => {
+                if let Some(hop_limit) = hop_limit {
+                    self.swap_hop_limit(&socket, hop_limit)?;
+                }
+                return Err(e);
+            }
+            tos => tos.transpose()?,
+        };
+        let sent = self.inner.try_send_to(buf, *target);
+        if let Some(tos) = tos {
+            self.swap_tos(&socket, tos)?;
+        }
+        if let Some(hop_limit) = hop_limit {
+            self.swap_hop_limit(&socket, hop_limit)?;
+        }
+        sent
+    }
+
+    /// Set the TTL (or hop limit) of the socket, returning the previous one.
+    fn swap_hop_limit(&self, socket: &socket2::SockRef<'_>, hop_limit: u32) -> io::Result<u32> {
+        match self.kind {
+            ICMP::V4 => {
+                let previous = socket.ttl()?;
+                socket
+                    .set_ttl(hop_limit)
+                    .map_err(SocketOptionError::wrap("IP_TTL"))?;
+                Ok(previous)
+            }
+            ICMP::V6 => {
+                let previous = socket.unicast_hops_v6()?;
+                socket
+                    .set_unicast_hops_v6(hop_limit)
+                    .map_err(SocketOptionError::wrap("IPV6_UNICAST_HOPS"))?;
+                Ok(previous)
+            }
+        }
+    }
+
+    /// Set the TOS of the socket, returning the previous one. Only IPv4 sockets have one
+    /// outside of Linux.
+    fn swap_tos(&self, socket: &socket2::SockRef<'_>, tos: u32) -> io::Result<u32> {
+        match self.kind {
+            #[cfg(not(any(
+                target_os = "fuchsia",
+                target_os = "redox",
+                target_os = "solaris",
+                target_os = "illumos",
+            )))]
+            ICMP::V4 => {
+                let previous = socket.tos()?;
+                socket
+                    .set_tos(tos)
+                    .map_err(SocketOptionError::wrap("IP_TOS"))?;
+                Ok(previous)
+            }
+            #[cfg(any(
+                target_os = "fuchsia",
+                target_os = "redox",
+                target_os = "solaris",
+                target_os = "illumos",
+            ))]
+            ICMP::V4 => Err(SocketOptionError::unsupported("IP_TOS")),
+            ICMP::V6 => Err(SocketOptionError::unsupported("IPV6_TCLASS")),
+        }
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl AsyncSocket {
+    /// Queue the request for the flush task, starting one if there is none. The task
+    /// runs once the current task yields, so requests sent together go out together.
+    async fn send_batched(
+        &self,
+        batch: &Arc<Batch>,
+        buf: &[u8],
+        target: &SocketAddr,
+        options: SendOptions,
+    ) -> io::Result<SendMeta> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut outgoing = batch.outgoing.lock();
+            outgoing.queued.push_back(Queued {
+                buf: buf.to_vec(),
+                target: *target,
+                options,
+                tx,
+            });
+            if !outgoing.flushing {
+                outgoing.flushing = true;
+                task::spawn(self.clone().flush(batch.clone()));
+            }
+        }
+        rx.await.unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "the runtime shut down before the request was sent",
+            ))
+        })
+    }
+
+    /// Send the queue a batch at a time until it is empty.
+    async fn flush(self, batch: Arc<Batch>) {
+        loop {
+            let queued: VecDeque<Queued> = {
+                let mut outgoing = batch.outgoing.lock();
+                if outgoing.queued.is_empty() {
+                    outgoing.flushing = false;
+                    return;
+                }
+                let len = outgoing.queued.len().min(batch.size);
+                outgoing.queued.drain(..len).collect()
+            };
+            self.send_queued(queued).await;
+        }
+    }
+
+    /// `sendmmsg` the queued requests, telling each of them how it went.
+    async fn send_queued(&self, mut queued: VecDeque<Queued>) {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = self.inner.as_raw_fd();
+        while !queued.is_empty() {
+            if let Err(e) = self.inner.writable().await {
+                for request in queued {
+                    let _ = request
+                        .tx
+                        .send(Err(io::Error::new(e.kind(), e.to_string())));
+                }
+                return;
+            }
+            let _guard = self.tx_timestamps.as_ref().map(|lock| lock.lock());
+            let result = {
+                let mut outgoing: Vec<_> = queued
+                    .iter()
+                    .map(|request| {
+                        sys::Outgoing::new(&request.buf, &request.target, &request.options)
+                    })
+                    .collect();
+                self.inner
+                    .try_io(Interest::WRITABLE, || sys::send_mmsg(fd, &mut outgoing))
+            };
+            match result {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                // `sendmmsg` only fails if the first message cannot be sent.
+                Err(e) => {
+                    if let Some(request) = queued.pop_front() {
+                        let _ = request.tx.send(Err(e));
+                    }
+                }
+                Ok(lens) => {
+                    let timestamps = if self.tx_timestamps.is_some() {
+                        let sent: Vec<&[u8]> = queued
+                            .iter()
+                            .take(lens.len())
+                            .map(|request| &request.buf[..])
+                            .collect();
+                        sys::tx_timestamps(fd, &sent)
+                    } else {
+                        vec![None; lens.len()]
+                    };
+                    for (len, timestamp) in lens.into_iter().zip(timestamps) {
+                        if let Some(request) = queued.pop_front() {
+                            let mut meta = SendMeta::new(len);
+                            meta.timestamp = timestamp;
+                            let _ = request.tx.send(Ok(meta));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hand out the messages of the last `recvmmsg`, reading the next batch once they are
+    /// gone.
+    async fn recv_batched(&self, batch: &Batch, buf: &mut [u8]) -> io::Result<RecvMeta> {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = self.inner.as_raw_fd();
+        loop {
+            {
+                let mut received = batch.received.lock();
+                if let Some(mut meta) = received.metas.pop_front() {
+                    let start = received.next_slot * SLOT;
+                    let len = meta.len.min(buf.len());
+                    buf[..len].copy_from_slice(&received.buf[start..start + len]);
+                    received.next_slot += 1;
+                    meta.len = len;
+                    return Ok(meta);
+                }
+            }
+            self.inner.readable().await?;
+            let mut received = batch.received.lock();
+            let received = &mut *received;
+            let metas = match self.inner.try_io(Interest::READABLE, || {
+                sys::recv_mmsg(fd, &mut received.buf, SLOT)
+            }) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                res => res?,
+            };
+            received.metas = metas.into();
+            received.next_slot = 0;
+        }
+    }
+}
+
+impl Transport for AsyncSocket {
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: &'a SocketAddr) -> TransportFuture<'a, usize> {
+        Box::pin(self.inner.send_to(buf, target))
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    fn send_msg<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: &'a SocketAddr,
+        options: SendOptions,
+    ) -> TransportFuture<'a, SendMeta> {
+        use std::os::unix::io::AsRawFd;
+
+        Box::pin(async move {
+            if let Some(batch) = &self.batch {
+                return self.send_batched(batch, buf, target, options).await;
+            }
+            let fd = self.inner.as_raw_fd();
+            loop {
+                self.inner.writable().await?;
+                let _guard = self.tx_timestamps.as_ref().map(|lock| lock.lock());
+                let len = match self.inner.try_io(Interest::WRITABLE, || {
+                    sys::send_msg(fd, buf, target, &options)
+                }) {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    res => res?,
+                };
+                let mut meta = SendMeta::new(len);
+                if self.tx_timestamps.is_some() {
+                    meta.timestamp = sys::tx_timestamp(fd, buf);
+                }
+                return Ok(meta);
+            }
+        })
+    }
+
+    /// Without control messages the TTL and TOS of `options` are set on the socket around
+    /// the send, see `send_with_socket_options`.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    fn send_msg<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: &'a SocketAddr,
+        options: SendOptions,
+    ) -> TransportFuture<'a, SendMeta> {
+        Box::pin(async move {
+            loop {
+                self.inner.writable().await?;
+                let _guard = self.send_lock.lock();
+                match self.send_with_socket_options(buf, target, options) {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    res => return res.map(SendMeta::new),
+                }
+            }
+        })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(self.inner.recv_from(buf))
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    fn recv_msg<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, RecvMeta> {
+        use std::os::unix::io::AsRawFd;
+
+        Box::pin(async move {
+            if let Some(batch) = &self.batch {
+                return self.recv_batched(batch, buf).await;
+            }
+            let fd = self.inner.as_raw_fd();
+            loop {
+                self.inner.readable().await?;
+                match self
+                    .inner
+                    .try_io(Interest::READABLE, || sys::recv_msg(fd, buf))
+                {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    res => return res,
+                }
+            }
         })
     }
 
-    pub(crate) async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
-        self.inner.recv_from(buf).await
+    /// The socket type that was actually opened, `Type::RAW` or `Type::DGRAM`.
+    fn sock_type(&self) -> Type {
+        self.sock_type
+    }
+
+    fn kind(&self) -> ICMP {
+        self.kind
+    }
+}
+
+/// Stands in for a socket `DualStackClient::new` could not open, failing every request
+/// with the reason.
+struct Unavailable {
+    kind: ICMP,
+    error: io::ErrorKind,
+    reason: String,
+}
+
+impl Unavailable {
+    fn new(kind: ICMP, error: &io::Error) -> Self {
+        Unavailable {
+            kind,
+            error: error.kind(),
+            reason: format!("no {:?} socket: {}", kind, error),
+        }
     }
 
-    pub(crate) async fn send_to(&self, buf: &mut [u8], target: &SocketAddr) -> io::Result<usize> {
-        self.inner.send_to(buf, target).await
+    fn error(&self) -> io::Error {
+        io::Error::new(self.error, self.reason.clone())
+    }
+}
+
+impl Transport for Unavailable {
+    fn send_to<'a>(&'a self, _: &'a [u8], _: &'a SocketAddr) -> TransportFuture<'a, usize> {
+        Box::pin(async move { Err(self.error()) })
+    }
+
+    fn send_msg<'a>(
+        &'a self,
+        _: &'a [u8],
+        _: &'a SocketAddr,
+        _: SendOptions,
+    ) -> TransportFuture<'a, SendMeta> {
+        Box::pin(async move { Err(self.error()) })
+    }
+
+    fn recv_from<'a>(&'a self, _: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(std::future::pending())
+    }
+
+    fn sock_type(&self) -> Type {
+        Type::DGRAM
+    }
+
+    fn kind(&self) -> ICMP {
+        self.kind
     }
 }
 
@@ -87,14 +684,21 @@ impl AsyncSocket {
 ///
 #[derive(Clone)]
 pub struct Client {
-    socket: AsyncSocket,
-    mapping: Arc<Mutex<HashMap<Uuid, mpsc::Sender<Message>>>>,
-    shutdown_tx: broadcast::Sender<()>,
+    socket: Arc<dyn Transport>,
+    mapping: Mapping,
+    counters: Arc<Counters>,
+    limiter: Option<Arc<RateLimiter>>,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    _shutdown: Arc<Shutdown>,
 }
 
-impl Drop for Client {
+/// Stops the receive task once the last clone of the `Client` is dropped.
+struct Shutdown(broadcast::Sender<()>);
+
+impl Drop for Shutdown {
     fn drop(&mut self) {
-        if self.shutdown_tx.send(()).is_err() {
+        if self.0.send(()).is_err() {
             warn!("Client shutdown error.");
         }
     }
@@ -105,54 +709,265 @@ impl Client {
     /// and you can clone to any `task` at will.
     pub async fn new(config: &Config) -> io::Result<Self> {
         let socket = AsyncSocket::new(config)?;
-        let mapping = Arc::new(Mutex::new(HashMap::new()));
+        Ok(Self::with_transport_config(socket, config))
+    }
+
+    /// A client sending and receiving over any [`Transport`](trait.Transport.html)
+    /// instead of an ICMP socket, e.g. a [`MockNetwork`](mock/struct.MockNetwork.html)
+    /// in tests.
+    pub fn with_transport<T: Transport>(transport: T) -> Self {
+        Self::with_transport_config(transport, &Config::default())
+    }
+
+    /// Like [`with_transport`](#method.with_transport), applying the settings of `config`
+    /// that belong to the client rather than the socket, such as rate limits.
+    pub fn with_transport_config<T: Transport>(transport: T, config: &Config) -> Self {
+        let socket: Arc<dyn Transport> = Arc::new(transport);
+        let mapping = Mapping::new();
+        let counters = Arc::new(Counters::default());
         let (shutdown_tx, _) = broadcast::channel(1);
         task::spawn(recv_task(
             socket.clone(),
             mapping.clone(),
+            counters.clone(),
             shutdown_tx.subscribe(),
         ));
+        #[cfg(feature = "metrics")]
+        let metrics = config.metrics.clone().unwrap_or_default();
+        #[cfg(feature = "metrics")]
+        metrics.register(counters.clone());
 
-        Ok(Self {
+        Self {
             socket,
             mapping,
-            shutdown_tx,
-        })
+            counters,
+            limiter: RateLimiter::new(config.rate_limit, config.destination_rate_limit)
+                .map(Arc::new),
+            #[cfg(feature = "metrics")]
+            metrics,
+            _shutdown: Arc::new(Shutdown(shutdown_tx)),
+        }
     }
 
     /// Create a `Pinger` instance, you can make special configuration for this instance. Such as `timeout`, `size` etc.
     pub async fn pinger(&self, host: IpAddr) -> Pinger {
-        let (tx, rx) = mpsc::channel(10);
-        let key = Uuid::new_v4();
+        #[allow(unused_mut)]
+        let mut pinger = Pinger::new(
+            host,
+            self.socket.clone(),
+            self.limiter.clone(),
+            self.mapping.clone(),
+        );
+        #[cfg(feature = "metrics")]
         {
-            self.mapping.lock().await.insert(key, tx);
+            pinger.metrics = Some(self.metrics.clone());
+        }
+        pinger
+    }
+
+    /// Resolve `host` (a name or an address) and create a pinger for one of its addresses,
+    /// chosen and kept up to date according to `plan`, see
+    /// [`DualStackClient::pinger_for_host`](struct.DualStackClient.html#method.pinger_for_host).
+    ///
+    /// The socket of a `Client` only reaches one address family, so only addresses of that
+    /// family are picked, whatever the preference of `plan`. A `plan` restricted to the
+    /// other family fails with `ErrorKind::InvalidInput`.
+    pub async fn pinger_for_host(&self, host: &str, plan: ResolvePlan) -> io::Result<HostPinger> {
+        let family = match (self.socket.kind(), plan.family) {
+            (ICMP::V4, FamilyPreference::V6Only) | (ICMP::V6, FamilyPreference::V4Only) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the plan only accepts addresses of the other family than the client",
+                ));
+            }
+            (ICMP::V4, _) => FamilyPreference::V4Only,
+            (ICMP::V6, _) => FamilyPreference::V6Only,
+        };
+        HostPinger::new(Clients::Single(self.clone()), host, plan.family(family)).await
+    }
+
+    /// How many received messages were delivered to a `Pinger` and how many were dropped,
+    /// counted since the `Client` was created and shared by its clones.
+    ///
+    /// Delivering never waits for a `Pinger`, a reply nobody waits for anymore is counted
+    /// as unmatched and dropped.
+    pub fn dispatch_stats(&self) -> DispatchStats {
+        self.counters.snapshot()
+    }
+
+    /// The registry the pingers of this client record into, see
+    /// [`Metrics`](struct.Metrics.html). Only available with the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// How long requests waited for the rate limits set in the `Config`, counted since
+    /// the `Client` was created and shared by its clones. All zero without limits.
+    pub fn rate_limit_stats(&self) -> RateLimitStats {
+        self.limiter
+            .as_ref()
+            .map(|limiter| limiter.stats())
+            .unwrap_or_default()
+    }
+
+    /// Find out which hosts of `ranges` are alive, like `fping -g`:
+    ///
+    /// ```rust,no_run
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// use surge_ping::{Client, Config, SweepPlan};
+    ///
+    /// let client = Client::new(&Config::default()).await?;
+    /// let report = client.sweep(&["10.0.0.0/22".parse()?], SweepPlan::new()).await;
+    /// for addr in report.alive() {
+    ///     println!("{} is alive", addr);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Addresses of the other family than the socket are reported as `HostStatus::Failed`,
+    /// use [`DualStackClient::sweep`](struct.DualStackClient.html#method.sweep) for mixed
+    /// lists.
+    pub async fn sweep(&self, ranges: &[IpRange], plan: SweepPlan) -> SweepReport {
+        sweep::sweep(|addr| self.pinger(addr), ranges, plan).await
+    }
+}
+
+/// A pair of `Client`s, one per address family, so IPv4 and IPv6 targets can be pinged
+/// side by side:
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use surge_ping::{Config, DualStackClient};
+///
+/// let client = DualStackClient::new(&Config::default()).await?;
+/// for host in ["192.0.2.1", "2001:db8::1"] {
+///     let pinger = client.pinger(host.parse()?).await;
+///     println!("{:?}", pinger.ping(0).await);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DualStackClient {
+    v4: Client,
+    v6: Client,
+    missing: Option<ICMP>,
+}
+
+impl DualStackClient {
+    /// Open an ICMPv4 and an ICMPv6 socket configured by `config`, ignoring its `kind`.
+    /// The `bind` address only applies to the socket of its family.
+    ///
+    /// This only fails if neither socket can be opened. On a host without IPv6, say,
+    /// requests to IPv6 addresses fail with the error of opening their socket and
+    /// [`missing`](#method.missing) tells which family that is.
+    pub async fn new(config: &Config) -> io::Result<Self> {
+        let mut v4 = config.clone();
+        v4.kind = ICMP::V4;
+        v4.bind = v4.bind.filter(|addr| addr.as_socket_ipv4().is_some());
+        let mut v6 = config.clone();
+        v6.kind = ICMP::V6;
+        v6.bind = v6.bind.filter(|addr| addr.as_socket_ipv6().is_some());
+        let (v4, v6, missing) = match (Client::new(&v4).await, Client::new(&v6).await) {
+            (Ok(v4), Ok(v6)) => (v4, v6, None),
+            (Ok(v4), Err(e)) => {
+                warn!("no ICMPv6 socket, IPv6 requests will fail: {}", e);
+                let v6 = Client::with_transport(Unavailable::new(ICMP::V6, &e));
+                (v4, v6, Some(ICMP::V6))
+            }
+            (Err(e), Ok(v6)) => {
+                warn!("no ICMPv4 socket, IPv4 requests will fail: {}", e);
+                let v4 = Client::with_transport(Unavailable::new(ICMP::V4, &e));
+                (v4, v6, Some(ICMP::V4))
+            }
+            (Err(e), Err(_)) => return Err(e),
+        };
+        Ok(Self { v4, v6, missing })
+    }
+
+    /// A client sending IPv4 requests over `v4` and IPv6 requests over `v6`.
+    pub fn with_transports<T4: Transport, T6: Transport>(v4: T4, v6: T6) -> Self {
+        Self {
+            v4: Client::with_transport(v4),
+            v6: Client::with_transport(v6),
+            missing: None,
+        }
+    }
+
+    /// The family whose socket [`new`](#method.new) could not open, if any.
+    pub fn missing(&self) -> Option<ICMP> {
+        self.missing
+    }
+
+    /// Create a `Pinger` on the client of the family of `host`.
+    pub async fn pinger(&self, host: IpAddr) -> Pinger {
+        self.client(host).pinger(host).await
+    }
+
+    /// Resolve `host` (a name or an address) and create a pinger for one of its addresses,
+    /// chosen and kept up to date according to `plan`.
+    ///
+    /// ```rust,no_run
+    /// # async fn run(client: surge_ping::DualStackClient) -> std::io::Result<()> {
+    /// use std::time::Duration;
+    ///
+    /// use surge_ping::{FamilyPreference, ResolvePlan};
+    ///
+    /// let plan = ResolvePlan::new()
+    ///     .family(FamilyPreference::PreferV6)
+    ///     .refresh(Duration::from_secs(300));
+    /// let mut pinger = client.pinger_for_host("example.com", plan).await?;
+    /// for seq in 0..10 {
+    ///     let (addr, outcome) = pinger.ping(seq).await;
+    ///     println!("{}: {:?}", addr, outcome);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Only addresses of the families with a socket are picked, as with
+    /// [`Client::pinger_for_host`](struct.Client.html#method.pinger_for_host).
+    pub async fn pinger_for_host(&self, host: &str, plan: ResolvePlan) -> io::Result<HostPinger> {
+        match self.missing {
+            Some(ICMP::V4) => self.v6.pinger_for_host(host, plan).await,
+            Some(ICMP::V6) => self.v4.pinger_for_host(host, plan).await,
+            None => HostPinger::new(Clients::DualStack(self.clone()), host, plan).await,
+        }
+    }
+
+    /// Find out which hosts of `ranges` are alive, IPv4 and IPv6 alike, see
+    /// [`Client::sweep`](struct.Client.html#method.sweep).
+    pub async fn sweep(&self, ranges: &[IpRange], plan: SweepPlan) -> SweepReport {
+        sweep::sweep(|addr| self.pinger(addr), ranges, plan).await
+    }
+
+    /// The client handling the family of `host`.
+    pub fn client(&self, host: IpAddr) -> &Client {
+        match host {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
         }
-        Pinger::new(host, self.socket.clone(), rx, key, self.mapping.clone())
     }
 }
 
 async fn recv_task(
-    socket: AsyncSocket,
-    mapping: Arc<Mutex<HashMap<Uuid, mpsc::Sender<Message>>>>,
+    socket: Arc<dyn Transport>,
+    mapping: Mapping,
+    counters: Arc<Counters>,
     mut shutdown_rx: broadcast::Receiver<()>,
 ) {
     let mut buf = [0; 2048];
+    let sock_type = socket.sock_type();
 
     loop {
         tokio::select! {
-            response = socket.recv_from(&mut buf) => {
-                if let Ok((sz, addr)) = response {
-                    let datas = buf[0..sz].to_vec();
-                    if let Some(uuid) = gen_uuid_with_payload(addr.ip(), datas.as_slice()) {
-                        let instant = Instant::now();
-                        let mut w = mapping.lock().await;
-                        if let Some(tx) = (*w).get(&uuid) {
-                            if tx.send(Message::new(instant, datas)).await.is_err() {
-                                warn!("Pinger({}) already closed.", addr);
-                                (*w).remove(&uuid);
-                            }
-                        }
-                    }
+            response = socket.recv_msg(&mut buf) => {
+                if let Ok(meta) = response {
+                    let datas = buf[0..meta.len].to_vec();
+                    let message = Message::new(Instant::now(), datas, meta);
+                    dispatch(&mapping, &counters, sock_type, message);
                 }
             }
             _ = shutdown_rx.recv() => {
@@ -162,36 +977,272 @@ async fn recv_task(
     }
 }
 
-fn gen_uuid_with_payload(addr: IpAddr, datas: &[u8]) -> Option<Uuid> {
-    match addr {
-        IpAddr::V4(_) => {
-            if let Some(ip_packet) = ipv4::Ipv4Packet::new(datas) {
-                if let Some(icmp_packet) = icmp::IcmpPacket::new(ip_packet.payload()) {
-                    let payload = icmp_packet.payload();
+/// Hand a received message to the request it answers. This never waits, so a `Pinger`
+/// that is slow to poll its requests cannot hold up the others.
+fn dispatch(mapping: &Mapping, counters: &Counters, sock_type: Type, message: Message) {
+    if is_informational_v6(&message) {
+        // Neighbor Discovery and the like, which reach raw IPv6 sockets without a filter.
+        counters.received();
+        counters.unmatched();
+        return;
+    }
+    let packet = match decode(&message, sock_type) {
+        Ok(packet) => packet,
+        Err(SurgeError::EchoRequestPacket) => return,
+        Err(e) => {
+            counters.received();
+            counters.malformed();
+            debug!("drop packet from {}: {}", message.addr, e);
+            return;
+        }
+    };
+    counters.received();
+    let request = match Request::parse(message.addr, sock_type, &message.packet) {
+        Some(request) => request,
+        None => {
+            counters.malformed();
+            debug!("drop packet from {}: no echo request found", message.addr);
+            return;
+        }
+    };
 
-                    if payload.len() < 20 {
-                        return None;
-                    }
+    let (by_ident, caches) = request.caches(mapping, sock_type);
+    let mut packet = packet;
+    for cache in caches {
+        match cache.dispatch(packet, message.when, by_ident) {
+            Ok(Delivery::Delivered) => {
+                counters.delivered();
+                return;
+            }
+            Ok(Delivery::Dropped) => {
+                counters.dropped();
+                return;
+            }
+            // Duplicate and late replies still answer no outstanding request.
+            Ok(Delivery::Reported) => {
+                counters.unmatched();
+                return;
+            }
+            Err(unmatched) => packet = unmatched,
+        }
+    }
+    counters.unmatched();
+}
 
-                    let uuid = &payload[4..20];
-                    return Uuid::from_slice(uuid).ok();
-                }
+/// Whether `message` is an ICMPv6 informational message other than an echo request or
+/// reply, which answers no request.
+fn is_informational_v6(message: &Message) -> bool {
+    message.addr.is_ipv6() && message.packet.first().is_some_and(|kind| *kind > 129)
+}
+
+fn decode(message: &Message, sock_type: Type) -> Result<IcmpPacket, SurgeError> {
+    match message.addr {
+        IpAddr::V4(src_addr) => Icmpv4Packet::decode_with(&message.packet, sock_type, src_addr)
+            .map(|mut packet| {
+                packet.recv_meta(&message.meta);
+                IcmpPacket::V4(packet)
+            }),
+        IpAddr::V6(src_addr) => {
+            Icmpv6Packet::decode(&message.packet, src_addr).map(|mut packet| {
+                packet.recv_meta(&message.meta);
+                IcmpPacket::V6(packet)
+            })
+        }
+    }
+}
+
+/// The echo request a received message answers, as far as needed to find its `Pinger`.
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    destination: IpAddr,
+    ident: u16,
+    /// The copy of the identifier at the start of the payload, if the payload made it back.
+    token: Option<u16>,
+}
+
+impl Request {
+    fn parse(addr: IpAddr, sock_type: Type, datas: &[u8]) -> Option<Request> {
+        // Only RAW IPv4 sockets hand us the IP header, DGRAM sockets strip it and the
+        // IPv6 header is never delivered to ICMPv6 sockets.
+        let ip_header_len = match addr {
+            IpAddr::V4(_) if sock_type == Type::RAW => {
+                ipv4::Ipv4Packet::new(datas)?.get_header_length() as usize * 4
+            }
+            _ => 0,
+        };
+        let icmp = datas.get(ip_header_len..)?;
+
+        // ICMP errors quote the IP header and the start of our echo request after their
+        // own 8 bytes of header.
+        let (destination, request) = match (addr, *icmp.first()?) {
+            (IpAddr::V4(_), 0) | (IpAddr::V6(_), 129) => (addr, icmp),
+            (IpAddr::V4(_), _) => {
+                let quoted = ipv4::Ipv4Packet::new(icmp.get(8..)?)?;
+                let header_len = quoted.get_header_length() as usize * 4;
+                let request = icmp.get(8 + header_len..)?;
+                (IpAddr::V4(quoted.get_destination()), request)
             }
+            (IpAddr::V6(_), _) => {
+                let destination: [u8; 16] = icmp.get(32..48)?.try_into().ok()?;
+                (IpAddr::from(destination), icmp.get(48..)?)
+            }
+        };
+
+        // icmp type(1) + code(1) + checksum(2) + identifier(2) + sequence(2), then the token
+        let ident = u16::from_be_bytes(request.get(4..6)?.try_into().ok()?);
+        let token = request
+            .get(8..10)
+            .map(|token| u16::from_be_bytes([token[0], token[1]]));
+        Some(Request {
+            destination,
+            ident,
+            token,
+        })
+    }
+
+    /// The caches that may hold the request, and whether its identifier can be trusted.
+    fn caches(&self, mapping: &Mapping, sock_type: Type) -> (bool, Vec<Cache>) {
+        // DGRAM sockets have the identifier rewritten by the kernel.
+        if sock_type == Type::RAW {
+            if let Some(cache) = mapping.get((self.destination, self.ident)) {
+                return (true, vec![cache]);
+            }
+        }
+        match self.token {
+            // A token nobody registered belongs to another process pinging the host.
+            Some(token) => (
+                false,
+                mapping.get((self.destination, token)).into_iter().collect(),
+            ),
+            // No payload to tell pingers for the destination apart, try them all.
+            None => (false, mapping.destination(self.destination)),
         }
-        IpAddr::V6(_) => {
-            if let Some(ipv6_packet) = ipv6::Ipv6Packet::new(datas) {
-                if let Some(icmpv6_packet) = icmpv6::Icmpv6Packet::new(ipv6_packet.payload()) {
-                    let payload = icmpv6_packet.payload();
+    }
+}
 
-                    if payload.len() < 20 {
-                        return None;
-                    }
+#[cfg(all(test, any(target_os = "android", target_os = "linux")))]
+mod tests {
+    use std::{net::UdpSocket as StdUdpSocket, os::unix::io::AsRawFd, time::Duration};
 
-                    let uuid = &payload[4..20];
-                    return Uuid::from_slice(uuid).ok();
-                }
+    use futures::future::join_all;
+    use tokio::time::timeout;
+
+    use super::*;
+
+    /// A batching socket over UDP, which `sendmmsg` and `recvmmsg` treat like ICMP.
+    fn batching_socket(size: usize) -> AsyncSocket {
+        let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        AsyncSocket {
+            inner: Arc::new(UdpSocket::from_std(socket).unwrap()),
+            sock_type: Type::DGRAM,
+            kind: ICMP::V4,
+            tx_timestamps: None,
+            batch: Some(Arc::new(Batch::new(size))),
+        }
+    }
+
+    fn peer() -> (StdUdpSocket, SocketAddr) {
+        let peer = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = peer.local_addr().unwrap();
+        (peer, addr)
+    }
+
+    #[tokio::test]
+    async fn partial_sendmmsg_sends_the_rest() {
+        let socket = batching_socket(8);
+        let (peer, target) = peer();
+        // an IPv6 target fails on an IPv4 socket, after the messages in front of it
+        let unreachable: SocketAddr = "[::1]:9".parse().unwrap();
+
+        let fd = socket.inner.as_raw_fd();
+        let options = SendOptions::default();
+        let mut outgoing = vec![
+            sys::Outgoing::new(b"first", &target, &options),
+            sys::Outgoing::new(b"unreachable", &unreachable, &options),
+            sys::Outgoing::new(b"last", &target, &options),
+        ];
+        assert_eq!(sys::send_mmsg(fd, &mut outgoing).unwrap(), vec![5]);
+        let mut buf = [0; 64];
+        let (len, _) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"first");
+
+        let messages: [(&[u8], SocketAddr); 5] = [
+            (b"a", target),
+            (b"bb", target),
+            (b"unreachable", unreachable),
+            (b"ccc", target),
+            (b"dddd", target),
+        ];
+        let results = join_all(
+            messages
+                .iter()
+                .map(|(buf, target)| socket.send_msg(buf, target, SendOptions::default())),
+        )
+        .await;
+        let lens: Vec<_> = results
+            .iter()
+            .map(|result| result.as_ref().ok().map(|meta| meta.len))
+            .collect();
+        assert_eq!(lens, vec![Some(1), Some(2), None, Some(3), Some(4)]);
+        for expected in [&b"a"[..], b"bb", b"ccc", b"dddd"] {
+            let (len, _) = peer.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..len], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn recvmmsg_reads_several_messages_at_once() {
+        let socket = batching_socket(4);
+        let local = socket.inner.local_addr().unwrap();
+        let (peer, peer_addr) = peer();
+        for i in 0..6u8 {
+            peer.send_to(&vec![i; usize::from(i) + 1], local).unwrap();
+        }
+
+        let mut buf = [0; 4];
+        for i in 0..6u8 {
+            let meta = socket.recv_msg(&mut buf).await.unwrap();
+            assert_eq!(meta.addr, peer_addr);
+            // longer messages are cut to the buffer
+            let len = usize::from(i + 1).min(buf.len());
+            assert_eq!(meta.len, len);
+            assert!(buf[..len].iter().all(|byte| *byte == i));
+            if i == 0 {
+                // the first call read a whole batch
+                let batch = socket.batch.as_ref().unwrap();
+                assert_eq!(batch.received.lock().metas.len(), 3);
             }
         }
     }
-    None
+
+    #[tokio::test]
+    async fn recv_waits_out_would_block() {
+        let socket = batching_socket(4);
+        let local = socket.inner.local_addr().unwrap();
+
+        let mut buf = [0; SLOT];
+        assert_eq!(
+            sys::recv_mmsg(socket.inner.as_raw_fd(), &mut buf, SLOT)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::WouldBlock
+        );
+        let mut buf = [0; 16];
+        assert!(
+            timeout(Duration::from_millis(50), socket.recv_msg(&mut buf))
+                .await
+                .is_err(),
+            "nothing to receive yet"
+        );
+
+        let (peer, _) = peer();
+        peer.send_to(b"late", local).unwrap();
+        let meta = timeout(Duration::from_secs(1), socket.recv_msg(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..meta.len], b"late");
+    }
 }
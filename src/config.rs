@@ -1,18 +1,60 @@
 use std::net::SocketAddr;
 
-use socket2::SockAddr;
+use socket2::{SockAddr, Type};
 
-use crate::ICMP;
+#[cfg(feature = "metrics")]
+use crate::Metrics;
+use crate::{RateLimit, ICMP};
 
 /// Config is the packaging of various configurations of `sockets`. If you want to make
 /// some `set_socket_opt` and other modifications, please define and implement them in `Config`.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
 pub struct Config {
+    pub sock_type_hint: Type,
     pub kind: ICMP,
     pub bind: Option<SockAddr>,
     pub interface: Option<String>,
+    pub vrf: Option<String>,
     pub ttl: Option<u32>,
+    pub tos: Option<u8>,
     pub fib: Option<u32>,
+    pub mark: Option<u32>,
+    pub freebind: bool,
+    pub transparent: bool,
+    pub kernel_timestamps: bool,
+    pub batch: usize,
+    pub recv_buffer_size: Option<usize>,
+    pub dont_fragment: bool,
+    pub rate_limit: Option<RateLimit>,
+    pub destination_rate_limit: Option<RateLimit>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<Metrics>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sock_type_hint: Type::RAW,
+            kind: ICMP::default(),
+            bind: None,
+            interface: None,
+            vrf: None,
+            ttl: None,
+            tos: None,
+            fib: None,
+            mark: None,
+            freebind: false,
+            transparent: false,
+            kernel_timestamps: false,
+            batch: 1,
+            recv_buffer_size: None,
+            dont_fragment: false,
+            rate_limit: None,
+            destination_rate_limit: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
 }
 
 impl Config {
@@ -26,13 +68,53 @@ impl Config {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
 pub struct ConfigBuilder {
+    sock_type_hint: Type,
     kind: ICMP,
     bind: Option<SockAddr>,
     interface: Option<String>,
+    vrf: Option<String>,
     ttl: Option<u32>,
+    tos: Option<u8>,
     fib: Option<u32>,
+    mark: Option<u32>,
+    freebind: bool,
+    transparent: bool,
+    kernel_timestamps: bool,
+    batch: usize,
+    recv_buffer_size: Option<usize>,
+    dont_fragment: bool,
+    rate_limit: Option<RateLimit>,
+    destination_rate_limit: Option<RateLimit>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self {
+            sock_type_hint: Type::RAW,
+            kind: ICMP::default(),
+            bind: None,
+            interface: None,
+            vrf: None,
+            ttl: None,
+            tos: None,
+            fib: None,
+            mark: None,
+            freebind: false,
+            transparent: false,
+            kernel_timestamps: false,
+            batch: 1,
+            recv_buffer_size: None,
+            dont_fragment: false,
+            rate_limit: None,
+            destination_rate_limit: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
 }
 
 impl ConfigBuilder {
@@ -55,20 +137,177 @@ impl ConfigBuilder {
         self
     }
 
+    /// Bind the socket to the master device of a VRF (`SO_BINDTODEVICE`), so requests are
+    /// routed with the table of the VRF and only replies arriving in it are received.
+    ///
+    /// Only available on Linux. The device is bound before `bind`, so the address given
+    /// there is checked against the VRF. It cannot be combined with `interface`, which
+    /// binds to a single device of the VRF instead.
+    pub fn vrf(mut self, vrf: &str) -> Self {
+        self.vrf = Some(vrf.to_string());
+        self
+    }
+
     /// Set the value of the `IP_TTL` option for this socket.
     ///
     /// This value sets the time-to-live field that is used in every packet sent
-    /// from this socket.
+    /// from this socket, unless the `Pinger` sets its own with `Pinger::ttl`.
     pub fn ttl(mut self, ttl: u32) -> Self {
         self.ttl = Some(ttl);
         self
     }
 
+    /// Set the TOS (ICMPv6: traffic class) byte of the IP header of the requests, DSCP in
+    /// the upper 6 bits and ECN in the lower 2, unless the `Pinger` sets its own with
+    /// `Pinger::tos`. (default: the one of the kernel, usually 0)
+    ///
+    /// Uses `IP_TOS` or `IPV6_TCLASS`, the latter only available on Linux.
+    pub fn tos(mut self, tos: u8) -> Self {
+        self.tos = Some(tos);
+        self
+    }
+
+    /// Set the DSCP of the requests, the upper 6 bits of `tos`, e.g. 46 for Expedited
+    /// Forwarding. Values above 63 are clamped to 63, the ECN bits are kept.
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        self.tos = Some((dscp.min(63) << 2) | (self.tos.unwrap_or(0) & 0b11));
+        self
+    }
+
+    /// Set the ECN codepoint of the requests, the lower 2 bits of `tos`. Values above 3
+    /// are clamped to 3, the DSCP is kept.
+    pub fn ecn(mut self, ecn: u8) -> Self {
+        self.tos = Some((self.tos.unwrap_or(0) & !0b11) | ecn.min(3));
+        self
+    }
+
     pub fn fib(mut self, fib: u32) -> Self {
         self.fib = Some(fib);
         self
     }
 
+    /// Set the value of the `SO_MARK` option for this socket, so requests can be routed
+    /// with policy rules matching the firewall mark (`ip rule add fwmark`).
+    ///
+    /// Only available on Linux, with `CAP_NET_ADMIN`.
+    pub fn mark(mut self, mark: u32) -> Self {
+        self.mark = Some(mark);
+        self
+    }
+
+    /// Allow `bind` to an address that is not (yet) assigned to the host, with
+    /// `IP_FREEBIND` or `IPV6_FREEBIND`. (default: false)
+    ///
+    /// Only available on Linux.
+    pub fn freebind(mut self, enable: bool) -> Self {
+        self.freebind = enable;
+        self
+    }
+
+    /// Allow `bind` to any address and send requests from it, as transparent proxies do,
+    /// with `IP_TRANSPARENT` or `IPV6_TRANSPARENT`. Replies only come back if they are
+    /// routed to the host, e.g. with a TPROXY rule. (default: false)
+    ///
+    /// Only available on Linux, with `CAP_NET_ADMIN` or `CAP_NET_RAW`.
+    pub fn transparent(mut self, enable: bool) -> Self {
+        self.transparent = enable;
+        self
+    }
+
+    /// The socket type tried first when creating the socket. (default: Type::RAW)
+    ///
+    /// `Type::RAW` requires root or `CAP_NET_RAW`. `Type::DGRAM` opens an unprivileged
+    /// ICMP datagram socket, which on Linux is allowed for groups listed in
+    /// `net.ipv4.ping_group_range`. If the hinted type is refused with a permission
+    /// error, the other type is tried before giving up.
+    ///
+    /// With `Type::DGRAM` the kernel owns the ICMP identifier, so the value set with
+    /// `Pinger::ident` is replaced on the wire.
+    pub fn sock_type_hint(mut self, typ: Type) -> Self {
+        self.sock_type_hint = typ;
+        self
+    }
+
+    /// Measure round trip times with the send and receive timestamps of the kernel
+    /// instead of the clock of the receive task, so scheduling delays do not count.
+    /// (default: false)
+    ///
+    /// Uses `SO_TIMESTAMPNS` and software `SO_TIMESTAMPING` transmit timestamps, only
+    /// available on Linux, creating the `Client` fails with `ErrorKind::Unsupported`
+    /// elsewhere. Requests whose transmit timestamp is not ready right after sending fall
+    /// back to the time taken once sending returned.
+    pub fn kernel_timestamps(mut self, enable: bool) -> Self {
+        self.kernel_timestamps = enable;
+        self
+    }
+
+    /// Send and receive up to `size` messages per system call with `sendmmsg` and
+    /// `recvmmsg`, at most 1024. (default: 1, one `sendmsg` or `recvmsg` per message)
+    ///
+    /// Only available on Linux, elsewhere it is ignored and every message is sent and
+    /// received with a system call of its own. Requests are queued and sent by a task
+    /// spawned for the queue, which saves system calls when many pingers of the `Client`
+    /// send at once at the cost of a little latency per request. The replies to such
+    /// bursts arrive in bursts as well, so the receive buffer is raised to 1 MiB unless
+    /// `recv_buffer_size` says otherwise.
+    pub fn batch(mut self, size: usize) -> Self {
+        self.batch = size.clamp(1, 1024);
+        self
+    }
+
+    /// Set the size of the receive buffer of the socket (`SO_RCVBUF`) in bytes. Replies
+    /// arriving while it is full are dropped by the kernel and their requests time out,
+    /// so raise it when many pingers of the `Client` ping at once. (default: the one of
+    /// the kernel, 1 MiB with `batch` on Linux)
+    ///
+    /// The kernel caps it at `net.core.rmem_max`, on loopback it holds the requests seen
+    /// by a `Type::RAW` socket as well.
+    pub fn recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set the Don't Fragment bit on IPv4 requests and forbid fragmenting IPv6 requests,
+    /// so requests larger than the path MTU fail instead of being fragmented.
+    /// (default: false, the defaults of the kernel apply)
+    ///
+    /// Only available on Linux, with `IP_MTU_DISCOVER` and `IPV6_DONTFRAG`, creating the
+    /// `Client` fails with `ErrorKind::Unsupported` elsewhere. Requests are not capped at
+    /// the path MTU the kernel learned either, only requests larger than the MTU of the
+    /// interface fail, with `EMSGSIZE`. See `Pinger::discover_mtu`.
+    pub fn dont_fragment(mut self, enable: bool) -> Self {
+        self.dont_fragment = enable;
+        self
+    }
+
+    /// Limit the requests of all pingers of the `Client` together, so large numbers of
+    /// pingers do not trip the ICMP rate limits of routers. (default: unlimited)
+    ///
+    /// Requests wait for a token before they are sent, the wait does not count towards
+    /// their timeout or round trip time. See `Client::rate_limit_stats` for how long they
+    /// waited.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Limit the requests to each destination on their own, on top of `rate_limit`.
+    /// (default: unlimited)
+    pub fn destination_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.destination_rate_limit = Some(limit);
+        self
+    }
+
+    /// Record into `metrics` instead of a registry of the `Client`'s own, so several
+    /// clients can be rendered together. (default: a new registry per `Client`)
+    ///
+    /// Only available with the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
     /// Identify which ICMP the socket handles.(default: ICMP::V4)
     pub fn kind(mut self, kind: ICMP) -> Self {
         self.kind = kind;
@@ -77,11 +316,25 @@ impl ConfigBuilder {
 
     pub fn build(self) -> Config {
         Config {
+            sock_type_hint: self.sock_type_hint,
             kind: self.kind,
             bind: self.bind,
             interface: self.interface,
+            vrf: self.vrf,
             ttl: self.ttl,
+            tos: self.tos,
             fib: self.fib,
+            mark: self.mark,
+            freebind: self.freebind,
+            transparent: self.transparent,
+            kernel_timestamps: self.kernel_timestamps,
+            batch: self.batch,
+            recv_buffer_size: self.recv_buffer_size,
+            dont_fragment: self.dont_fragment,
+            rate_limit: self.rate_limit,
+            destination_rate_limit: self.destination_rate_limit,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
         }
     }
 }
@@ -0,0 +1,159 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash, Hasher},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::RwLock;
+
+use crate::ping::Cache;
+
+/// What the `Client` knows a `Pinger` by: its destination and ICMP identifier.
+pub(crate) type PingerKey = (IpAddr, u16);
+
+// Enough that creating and dropping pingers rarely waits on the receive task, which
+// only takes read locks.
+const SHARDS: usize = 64;
+
+/// The pingers of one destination with their identifiers, usually just one.
+type Pingers = Vec<(u16, Cache)>;
+
+/// The `Cache` of every `Pinger` of a `Client`, shared with its receive task.
+///
+/// Pingers are spread over shards by destination, so the pingers of one destination can
+/// be looked up together when a reply carries no usable identifier.
+#[derive(Clone)]
+pub(crate) struct Mapping {
+    inner: Arc<Shards>,
+}
+
+struct Shards {
+    hasher: RandomState,
+    shards: Vec<RwLock<HashMap<IpAddr, Pingers>>>,
+}
+
+impl Mapping {
+    pub(crate) fn new() -> Mapping {
+        Mapping {
+            inner: Arc::new(Shards {
+                hasher: RandomState::new(),
+                shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+            }),
+        }
+    }
+
+    fn shard(&self, destination: &IpAddr) -> &RwLock<HashMap<IpAddr, Pingers>> {
+        let mut hasher = self.inner.hasher.build_hasher();
+        destination.hash(&mut hasher);
+        let hash = hasher.finish();
+        &self.inner.shards[hash as usize % SHARDS]
+    }
+
+    pub(crate) fn get(&self, (destination, ident): PingerKey) -> Option<Cache> {
+        self.shard(&destination)
+            .read()
+            .get(&destination)?
+            .iter()
+            .find(|(key, _)| *key == ident)
+            .map(|(_, cache)| cache.clone())
+    }
+
+    /// Every cache registered for `destination`.
+    pub(crate) fn destination(&self, destination: IpAddr) -> Vec<Cache> {
+        self.shard(&destination)
+            .read()
+            .get(&destination)
+            .map(|pingers| pingers.iter().map(|(_, cache)| cache.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Register `cache` under `key` unless another cache is, false if so. Checking and
+    /// registering under one lock keeps two pingers from taking the same key.
+    pub(crate) fn try_insert(&self, (destination, ident): PingerKey, cache: Cache) -> bool {
+        let mut shard = self.shard(&destination).write();
+        let pingers = shard.entry(destination).or_default();
+        if pingers.iter().any(|(key, _)| *key == ident) {
+            return false;
+        }
+        pingers.push((ident, cache));
+        true
+    }
+
+    /// Unregister `key` if `cache` is still the one registered under it.
+    pub(crate) fn remove(&self, (destination, ident): PingerKey, cache: &Cache) {
+        let mut shard = self.shard(&destination).write();
+        if let Some(pingers) = shard.get_mut(&destination) {
+            pingers.retain(|(key, registered)| *key != ident || !registered.same(cache));
+            if pingers.is_empty() {
+                shard.remove(&destination);
+            }
+        }
+    }
+}
+
+/// What the receive task of a [`Client`](struct.Client.html) did with the messages it
+/// read, see [`Client::dispatch_stats`](struct.Client.html#method.dispatch_stats).
+///
+/// The counters only grow, take the difference of two snapshots for a rate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DispatchStats {
+    /// Echo replies and ICMP errors read from the transport, echo requests a RAW socket
+    /// sees on loopback are not counted.
+    pub received: u64,
+    /// Messages handed to the request they answer.
+    pub delivered: u64,
+    /// Messages answering no outstanding request: duplicates, replies that came after
+    /// the timeout, or replies for another process pinging the same host.
+    pub unmatched: u64,
+    /// Messages too short or too broken to tell which request they answer.
+    pub malformed: u64,
+    /// Messages answering a request whose `PingHandle` was dropped while the message was
+    /// being handed over. Requests given up on earlier count their answer as unmatched.
+    pub dropped: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    received: AtomicU64,
+    delivered: AtomicU64,
+    unmatched: AtomicU64,
+    malformed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn delivered(&self) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn unmatched(&self) {
+        self.unmatched.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn malformed(&self) {
+        self.malformed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> DispatchStats {
+        DispatchStats {
+            received: self.received.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            unmatched: self.unmatched.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
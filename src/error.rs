@@ -1,8 +1,10 @@
 #![allow(dead_code)]
-use std::io;
+use std::{io, net::IpAddr, time::Duration};
 
 use thiserror::Error;
 
+use crate::{icmp::IcmpError, payload::PayloadMismatch};
+
 pub type Result<T> = std::result::Result<T, SurgeError>;
 
 /// An error resulting from a ping option-setting or send/receive operation.
@@ -17,6 +19,19 @@ pub enum SurgeError {
     IOError(#[from] io::Error),
     #[error("Request timeout for icmp_seq {seq}")]
     Timeout { seq: u16 },
+    #[error("From {from} icmp_seq={seq} {error}")]
+    IcmpError {
+        seq: u16,
+        from: IpAddr,
+        error: IcmpError,
+        rtt: Duration,
+    },
+    #[error("icmp_seq={seq} wrong data: {mismatch}")]
+    CorruptPayload {
+        seq: u16,
+        rtt: Duration,
+        mismatch: PayloadMismatch,
+    },
     #[error("Echo Request packet.")]
     EchoRequestPacket,
     #[error("Network error.")]
@@ -36,3 +51,42 @@ pub enum MalformedPacketError {
     #[error("payload too short, got {got}, want {want}")]
     PayloadTooShort { got: usize, want: usize },
 }
+
+/// A socket option `Client::new` could not apply, found as the inner error of the
+/// `io::Error` it returns, which keeps the kind of `source`:
+///
+/// ```rust,no_run
+/// # async fn run() {
+/// use surge_ping::{Client, Config, SocketOptionError};
+///
+/// if let Err(e) = Client::new(&Config::builder().mark(42).build()).await {
+///     match e.get_ref().and_then(|inner| inner.downcast_ref::<SocketOptionError>()) {
+///         Some(failed) => println!("{} could not be set: {}", failed.option, failed.source),
+///         None => println!("no socket: {}", e),
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Error, Debug)]
+#[error("failed to apply {option}: {source}")]
+pub struct SocketOptionError {
+    /// The option, such as `SO_MARK`, or `bind` for binding the source address.
+    pub option: &'static str,
+    #[source]
+    pub source: io::Error,
+}
+
+impl SocketOptionError {
+    /// Name `option` in the errors of setting it.
+    pub(crate) fn wrap(option: &'static str) -> impl FnOnce(io::Error) -> io::Error {
+        move |source| io::Error::new(source.kind(), SocketOptionError { option, source })
+    }
+
+    /// The error of an option this platform does not have.
+    pub(crate) fn unsupported(option: &'static str) -> io::Error {
+        Self::wrap(option)(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "not supported on this platform",
+        ))
+    }
+}
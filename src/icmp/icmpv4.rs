@@ -1,25 +1,22 @@
-use std::convert::TryInto;
 use std::net::Ipv4Addr;
 
 use pnet_packet::icmp::{self, IcmpCode, IcmpType};
 use pnet_packet::Packet;
 use pnet_packet::{ipv4, PacketSize};
+use socket2::Type;
 
 use crate::error::{MalformedPacketError, Result, SurgeError};
+use crate::icmp::{slice, IcmpError};
+use crate::transport::RecvMeta;
 
-pub fn make_icmpv4_echo_packet(
-    ident: u16,
-    seq_cnt: u16,
-    size: usize,
-    key: &[u8],
-) -> Result<Vec<u8>> {
-    let mut buf = vec![0; 8 + size]; // 8 bytes of header, then payload
+pub fn make_icmpv4_echo_packet(ident: u16, seq_cnt: u16, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = vec![0; 8 + payload.len()]; // 8 bytes of header, then payload
     let mut packet = icmp::echo_request::MutableEchoRequestPacket::new(&mut buf[..])
         .ok_or(SurgeError::IncorrectBufferSize)?;
     packet.set_icmp_type(icmp::IcmpTypes::EchoRequest);
     packet.set_identifier(ident);
     packet.set_sequence_number(seq_cnt);
-    packet.set_payload(key);
+    packet.set_payload(payload);
 
     // Calculate and set the checksum
     let icmp_packet =
@@ -36,12 +33,15 @@ pub struct Icmpv4Packet {
     source: Ipv4Addr,
     destination: Ipv4Addr,
     ttl: u8,
+    tos: Option<u8>,
     icmp_type: IcmpType,
     icmp_code: IcmpCode,
     size: usize,
     real_dest: Ipv4Addr,
     identifier: u16,
     sequence: u16,
+    payload: Vec<u8>,
+    error: Option<IcmpError>,
 }
 
 impl Default for Icmpv4Packet {
@@ -50,12 +50,15 @@ impl Default for Icmpv4Packet {
             source: Ipv4Addr::new(127, 0, 0, 1),
             destination: Ipv4Addr::new(127, 0, 0, 1),
             ttl: 0,
+            tos: None,
             icmp_type: IcmpType::new(0),
             icmp_code: IcmpCode::new(0),
             size: 0,
             real_dest: Ipv4Addr::new(127, 0, 0, 1),
             identifier: 0,
             sequence: 0,
+            payload: Vec::new(),
+            error: None,
         }
     }
 }
@@ -91,6 +94,17 @@ impl Icmpv4Packet {
         self.ttl
     }
 
+    fn tos(&mut self, tos: Option<u8>) -> &mut Self {
+        self.tos = tos;
+        self
+    }
+
+    /// Get the tos field, DSCP in the upper 6 bits and ECN in the lower 2. `None` if the
+    /// socket could not tell.
+    pub fn get_tos(&self) -> Option<u8> {
+        self.tos
+    }
+
     fn icmp_type(&mut self, icmp_type: IcmpType) -> &mut Self {
         self.icmp_type = icmp_type;
         self
@@ -152,11 +166,73 @@ impl Icmpv4Packet {
         self.sequence
     }
 
-    /// Decode into icmp packet from the socket message.
+    fn payload(&mut self, payload: &[u8]) -> &mut Self {
+        self.payload = payload.to_vec();
+        self
+    }
+
+    /// Get the payload echoed back by an echo reply, empty for other packets.
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    fn error(&mut self, error: IcmpError) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Get the decoded ICMP error if this is not an echo reply.
+    pub fn get_error(&self) -> Option<IcmpError> {
+        self.error
+    }
+
+    /// Fill in what the socket reported about the IPv4 header and the message did not
+    /// carry.
+    pub(crate) fn recv_meta(&mut self, meta: &RecvMeta) -> &mut Self {
+        if self.tos.is_none() {
+            self.tos = meta.tos;
+        }
+        self
+    }
+
+    /// Decode into icmp packet from the socket message, which starts with the IPv4 header
+    /// as delivered by `Type::RAW` sockets.
     pub fn decode(buf: &[u8]) -> Result<Self> {
-        let ipv4_packet = ipv4::Ipv4Packet::new(buf)
-            .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
-        let payload = ipv4_packet.payload();
+        Self::decode_with(buf, Type::RAW, Ipv4Addr::UNSPECIFIED)
+    }
+
+    /// Decode into icmp packet from the message of a socket of type `sock_type`.
+    ///
+    /// `Type::RAW` sockets deliver the IPv4 header in front of the ICMP message, while
+    /// `Type::DGRAM` sockets strip it. In the latter case `src_addr` (the address the
+    /// message was received from) is used as the source, the ttl is unknown (0) and so is
+    /// the tos.
+    pub fn decode_with(buf: &[u8], sock_type: Type, src_addr: Ipv4Addr) -> Result<Self> {
+        if sock_type == Type::RAW {
+            let header_len = ipv4_header_len(buf)?;
+            let ipv4_packet = ipv4::Ipv4Packet::new(buf)
+                .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
+            let mut packet = Self::decode_icmp(
+                &buf[header_len..],
+                ipv4_packet.get_source(),
+                ipv4_packet.get_destination(),
+                ipv4_packet.get_ttl(),
+            )?;
+            packet.tos(Some((ipv4_packet.get_dscp() << 2) | ipv4_packet.get_ecn()));
+            Ok(packet)
+        } else {
+            Self::decode_icmp(buf, src_addr, Ipv4Addr::UNSPECIFIED, 0)
+        }
+    }
+
+    fn decode_icmp(
+        payload: &[u8],
+        source: Ipv4Addr,
+        destination: Ipv4Addr,
+        ttl: u8,
+    ) -> Result<Self> {
+        // type(1) + code(1) + checksum(2) + identifier/unused(4)
+        let header = slice(payload, 0, 8)?;
         let icmp_packet = icmp::IcmpPacket::new(payload)
             .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIcmpv4Packet))?;
         match icmp_packet.get_icmp_type() {
@@ -165,38 +241,56 @@ impl Icmpv4Packet {
                     .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIcmpv4Packet))?;
                 let mut packet = Icmpv4Packet::default();
                 packet
-                    .source(ipv4_packet.get_source())
-                    .destination(ipv4_packet.get_destination())
-                    .ttl(ipv4_packet.get_ttl())
+                    .source(source)
+                    .destination(destination)
+                    .ttl(ttl)
                     .icmp_type(icmp_packet.get_icmp_type())
                     .icmp_code(icmp_packet.get_icmp_code())
                     .size(icmp_packet.packet().len())
-                    .real_dest(ipv4_packet.get_source())
+                    .real_dest(source)
                     .identifier(icmp_packet.get_identifier())
-                    .sequence(icmp_packet.get_sequence_number());
+                    .sequence(icmp_packet.get_sequence_number())
+                    .payload(icmp_packet.payload());
                 Ok(packet)
             }
             icmp::IcmpTypes::EchoRequest => Err(SurgeError::EchoRequestPacket),
             _ => {
-                let icmp_payload = icmp_packet.payload();
-                // icmp unused(4) + ip header(20) + echo icmp(4)
-                let real_ip_packet = ipv4::Ipv4Packet::new(&icmp_payload[4..])
+                // icmp header(8) + original ip header + original icmp echo header(8)
+                let quoted = &payload[8..];
+                let real_ip_len = ipv4_header_len(quoted)?;
+                let real_ip_packet = ipv4::Ipv4Packet::new(quoted)
                     .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
-                let identifier = u16::from_be_bytes(icmp_payload[28..30].try_into().unwrap());
-                let sequence = u16::from_be_bytes(icmp_payload[30..32].try_into().unwrap());
+                let echo = slice(quoted, real_ip_len, real_ip_len + 8)?;
+                let error = IcmpError::from_v4(
+                    icmp_packet.get_icmp_type().0,
+                    icmp_packet.get_icmp_code().0,
+                    [header[4], header[5], header[6], header[7]],
+                );
                 let mut packet = Icmpv4Packet::default();
                 packet
-                    .source(ipv4_packet.get_source())
-                    .destination(ipv4_packet.get_destination())
-                    .ttl(ipv4_packet.get_ttl())
+                    .source(source)
+                    .destination(destination)
+                    .ttl(ttl)
                     .icmp_type(icmp_packet.get_icmp_type())
                     .icmp_code(icmp_packet.get_icmp_code())
                     .size(icmp_packet.packet_size())
                     .real_dest(real_ip_packet.get_destination())
-                    .identifier(identifier)
-                    .sequence(sequence);
+                    .identifier(u16::from_be_bytes([echo[4], echo[5]]))
+                    .sequence(u16::from_be_bytes([echo[6], echo[7]]))
+                    .error(error);
                 Ok(packet)
             }
         }
     }
 }
+
+/// The length of the IPv4 header at the start of `buf`, checking that all of it is there.
+fn ipv4_header_len(buf: &[u8]) -> Result<usize> {
+    let version_ihl = slice(buf, 0, 20)?[0];
+    if version_ihl >> 4 != 4 || version_ihl & 0x0f < 5 {
+        return Err(MalformedPacketError::NotIpv4Packet.into());
+    }
+    let header_len = (version_ihl & 0x0f) as usize * 4;
+    slice(buf, 0, header_len)?;
+    Ok(header_len)
+}
@@ -1,26 +1,23 @@
-use std::convert::TryInto;
-use std::net::Ipv6Addr;
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv6Addr};
 
 use pnet_packet::icmpv6::{self, Icmpv6Code, Icmpv6Type};
 use pnet_packet::Packet;
 use pnet_packet::PacketSize;
 
 use crate::error::{MalformedPacketError, Result, SurgeError};
+use crate::icmp::{slice, IcmpError};
+use crate::transport::RecvMeta;
 
 #[allow(dead_code)]
-pub fn make_icmpv6_echo_packet(
-    ident: u16,
-    seq_cnt: u16,
-    size: usize,
-    key: &[u8],
-) -> Result<Vec<u8>> {
-    let mut buf = vec![0; 8 + size]; // 8 bytes of header, then payload
+pub fn make_icmpv6_echo_packet(ident: u16, seq_cnt: u16, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = vec![0; 8 + payload.len()]; // 8 bytes of header, then payload
     let mut packet = icmpv6::echo_request::MutableEchoRequestPacket::new(&mut buf[..])
         .ok_or(SurgeError::IncorrectBufferSize)?;
     packet.set_icmpv6_type(icmpv6::Icmpv6Types::EchoRequest);
     packet.set_identifier(ident);
     packet.set_sequence_number(seq_cnt);
-    packet.set_payload(key);
+    packet.set_payload(payload);
 
     // Per https://tools.ietf.org/html/rfc3542#section-3.1 the checksum is
     // omitted, the kernel will insert it.
@@ -34,26 +31,34 @@ pub struct Icmpv6Packet {
     source: Ipv6Addr,
     destination: Ipv6Addr,
     max_hop_limit: u8,
+    traffic_class: Option<u8>,
     icmpv6_type: Icmpv6Type,
     icmpv6_code: Icmpv6Code,
     size: usize,
     real_dest: Ipv6Addr,
     identifier: u16,
     sequence: u16,
+    payload: Vec<u8>,
+    error: Option<IcmpError>,
+    interface: Option<u32>,
 }
 
 impl Default for Icmpv6Packet {
     fn default() -> Self {
         Icmpv6Packet {
-            source: Ipv6Addr::LOCALHOST,
-            destination: Ipv6Addr::LOCALHOST,
+            source: Ipv6Addr::UNSPECIFIED,
+            destination: Ipv6Addr::UNSPECIFIED,
             max_hop_limit: 0,
+            traffic_class: None,
             icmpv6_type: Icmpv6Type::new(0),
             icmpv6_code: Icmpv6Code::new(0),
             size: 0,
-            real_dest: Ipv6Addr::LOCALHOST,
+            real_dest: Ipv6Addr::UNSPECIFIED,
             identifier: 0,
             sequence: 0,
+            payload: Vec::new(),
+            error: None,
+            interface: None,
         }
     }
 }
@@ -74,7 +79,8 @@ impl Icmpv6Packet {
         self
     }
 
-    /// Get the destination IPv6 address.
+    /// Get the local address the packet was received on, unspecified if the socket
+    /// could not tell.
     pub fn get_destination(&self) -> Ipv6Addr {
         self.destination
     }
@@ -84,11 +90,17 @@ impl Icmpv6Packet {
         self
     }
 
-    /// Get the hop_limit field.
+    /// Get the hop_limit field of the IPv6 header, 0 if the socket could not tell.
     pub fn get_max_hop_limit(&self) -> u8 {
         self.max_hop_limit
     }
 
+    /// Get the traffic class of the IPv6 header, DSCP in the upper 6 bits and ECN in the
+    /// lower 2. `None` if the socket could not tell.
+    pub fn get_traffic_class(&self) -> Option<u8> {
+        self.traffic_class
+    }
+
     fn icmpv6_type(&mut self, icmpv6_type: Icmpv6Type) -> &mut Self {
         self.icmpv6_type = icmpv6_type;
         self
@@ -150,44 +162,97 @@ impl Icmpv6Packet {
         self.sequence
     }
 
-    /// Decode into icmpv6 packet from the socket message.
-    pub fn decode(buf: &[u8], destination: Ipv6Addr) -> Result<Self> {
+    fn payload(&mut self, payload: &[u8]) -> &mut Self {
+        self.payload = payload.to_vec();
+        self
+    }
+
+    /// Get the payload echoed back by an echo reply, empty for other packets.
+    pub fn get_payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    fn error(&mut self, error: IcmpError) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Get the decoded ICMP error if this is not an echo reply.
+    pub fn get_error(&self) -> Option<IcmpError> {
+        self.error
+    }
+
+    /// Get the index of the interface the packet was received on.
+    pub fn get_interface_index(&self) -> Option<u32> {
+        self.interface
+    }
+
+    /// Fill in what the IPv6 header said, as reported by the socket.
+    pub(crate) fn recv_meta(&mut self, meta: &RecvMeta) -> &mut Self {
+        if let Some(hop_limit) = meta.hop_limit {
+            self.max_hop_limit(hop_limit);
+        }
+        self.traffic_class = meta.tos;
+        if let Some(IpAddr::V6(destination)) = meta.local_addr {
+            self.destination(destination);
+        }
+        self.interface = meta.interface;
+        self
+    }
+
+    /// Decode into icmpv6 packet from the socket message sent by `src_addr`.
+    ///
+    /// The IPv6 header is not part of the message, so the hop limit, the traffic class and
+    /// the destination are left unset.
+    pub fn decode(buf: &[u8], src_addr: Ipv6Addr) -> Result<Self> {
         // The IPv6 header is automatically cropped off when recvfrom() is used.
+        // type(1) + code(1) + checksum(2) + identifier/unused(4)
+        let header = slice(buf, 0, 8)?;
         let icmpv6_packet = icmpv6::Icmpv6Packet::new(buf)
             .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIcmpv6Packet))?;
-        let icmpv6_payload = icmpv6_packet.payload();
         match icmpv6_packet.get_icmpv6_type() {
             icmpv6::Icmpv6Types::EchoRequest => Err(SurgeError::EchoRequestPacket),
             icmpv6::Icmpv6Types::EchoReply => {
-                let identifier = u16::from_be_bytes(icmpv6_payload[0..2].try_into().unwrap());
-                let sequence = u16::from_be_bytes(icmpv6_payload[2..4].try_into().unwrap());
+                let identifier = u16::from_be_bytes([header[4], header[5]]);
+                let sequence = u16::from_be_bytes([header[6], header[7]]);
                 let mut packet = Icmpv6Packet::default();
                 packet
-                    .source(destination)
-                    .destination(Ipv6Addr::LOCALHOST)
-                    .max_hop_limit(0)
+                    .source(src_addr)
                     .icmpv6_type(icmpv6_packet.get_icmpv6_type())
                     .icmpv6_code(icmpv6_packet.get_icmpv6_code())
                     .size(icmpv6_packet.packet().len())
-                    .real_dest(destination)
+                    .real_dest(src_addr)
                     .identifier(identifier)
-                    .sequence(sequence);
+                    .sequence(sequence)
+                    .payload(&buf[8..]);
                 Ok(packet)
             }
             _ => {
-                // ipv6 header(40) + icmpv6 echo header(4)
-                let identifier = u16::from_be_bytes(icmpv6_payload[44..46].try_into().unwrap());
-                let sequence = u16::from_be_bytes(icmpv6_payload[46..48].try_into().unwrap());
+                // icmpv6 header(8) + original ipv6 header(40) + original icmpv6 echo header(8)
+                let quoted = slice(buf, 8, 56)?;
+                let real_dest = <[u8; 16]>::try_from(slice(buf, 32, 48)?).map_err(|_| {
+                    MalformedPacketError::PayloadTooShort {
+                        got: buf.len(),
+                        want: 48,
+                    }
+                })?;
+                let identifier = u16::from_be_bytes([quoted[44], quoted[45]]);
+                let sequence = u16::from_be_bytes([quoted[46], quoted[47]]);
+                let error = IcmpError::from_v6(
+                    icmpv6_packet.get_icmpv6_type().0,
+                    icmpv6_packet.get_icmpv6_code().0,
+                    [header[4], header[5], header[6], header[7]],
+                );
                 let mut packet = Icmpv6Packet::default();
                 packet
-                    .source(destination)
-                    .destination(destination)
-                    .max_hop_limit(0)
+                    .source(src_addr)
                     .icmpv6_type(icmpv6_packet.get_icmpv6_type())
                     .icmpv6_code(icmpv6_packet.get_icmpv6_code())
                     .size(icmpv6_packet.packet_size())
+                    .real_dest(Ipv6Addr::from(real_dest))
                     .identifier(identifier)
-                    .sequence(sequence);
+                    .sequence(sequence)
+                    .error(error);
                 Ok(packet)
             }
         }
@@ -1,8 +1,180 @@
-use std::net::IpAddr;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use pnet_packet::{icmp::IcmpTypes, icmpv6::Icmpv6Types};
+
+use crate::error::{MalformedPacketError, Result};
 
 pub mod icmpv4;
 pub mod icmpv6;
 
+/// `buf[start..end]`, or `PayloadTooShort` if `buf` ends before `end`.
+pub(crate) fn slice(buf: &[u8], start: usize, end: usize) -> Result<&[u8]> {
+    buf.get(start..end).ok_or_else(|| {
+        MalformedPacketError::PayloadTooShort {
+            got: buf.len(),
+            want: end,
+        }
+        .into()
+    })
+}
+
+/// An ICMP error message received in answer to an echo request, decoded from its type
+/// and code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IcmpError {
+    /// ICMPv4 type 3, ICMPv6 type 1.
+    DestinationUnreachable(Unreachable),
+    /// ICMPv6 type 2, the link to the next hop cannot carry a packet of this size.
+    PacketTooBig { mtu: u32 },
+    /// ICMPv4 type 11, ICMPv6 type 3.
+    TimeExceeded(TimeExceeded),
+    /// ICMPv4 type 12, ICMPv6 type 4, `pointer` is the offset of the offending byte.
+    ParameterProblem { pointer: u32 },
+    /// ICMPv4 type 5.
+    Redirect { gateway: Ipv4Addr },
+    /// ICMPv4 type 4.
+    SourceQuench,
+    /// Any other non-echo message.
+    Other { icmp_type: u8, icmp_code: u8 },
+}
+
+/// The reason of a Destination Unreachable message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Unreachable {
+    /// No route to the network (ICMPv6: no route to destination).
+    Network,
+    /// The host is unreachable (ICMPv6: address unreachable).
+    Host,
+    Protocol,
+    Port,
+    /// The packet needs fragmentation but has DF set, `next_hop_mtu` is 0 if the router
+    /// did not report it.
+    FragmentationNeeded {
+        next_hop_mtu: u16,
+    },
+    SourceRouteFailed,
+    /// Communication administratively prohibited, e.g. by a firewall.
+    AdminProhibited,
+    /// ICMPv6: beyond scope of the source address.
+    BeyondScope,
+    /// Any other code.
+    Other(u8),
+}
+
+/// The reason of a Time Exceeded message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TimeExceeded {
+    /// TTL or hop limit reached zero in transit.
+    Transit,
+    /// Fragment reassembly time exceeded.
+    Reassembly,
+}
+
+impl IcmpError {
+    /// Decode an ICMPv4 message that is not an echo reply. `rest` is the second 32-bit
+    /// word of the ICMP header.
+    pub(crate) fn from_v4(icmp_type: u8, icmp_code: u8, rest: [u8; 4]) -> Self {
+        match icmp_type {
+            3 => IcmpError::DestinationUnreachable(match icmp_code {
+                0 | 6 | 11 => Unreachable::Network,
+                1 | 7 | 12 => Unreachable::Host,
+                2 => Unreachable::Protocol,
+                3 => Unreachable::Port,
+                4 => Unreachable::FragmentationNeeded {
+                    next_hop_mtu: u16::from_be_bytes([rest[2], rest[3]]),
+                },
+                5 => Unreachable::SourceRouteFailed,
+                9 | 10 | 13 => Unreachable::AdminProhibited,
+                code => Unreachable::Other(code),
+            }),
+            4 => IcmpError::SourceQuench,
+            5 => IcmpError::Redirect {
+                gateway: Ipv4Addr::from(rest),
+            },
+            11 => IcmpError::TimeExceeded(if icmp_code == 1 {
+                TimeExceeded::Reassembly
+            } else {
+                TimeExceeded::Transit
+            }),
+            12 => IcmpError::ParameterProblem {
+                pointer: u32::from(rest[0]),
+            },
+            _ => IcmpError::Other {
+                icmp_type,
+                icmp_code,
+            },
+        }
+    }
+
+    /// Decode an ICMPv6 message that is not an echo reply. `rest` is the second 32-bit
+    /// word of the ICMPv6 header.
+    pub(crate) fn from_v6(icmp_type: u8, icmp_code: u8, rest: [u8; 4]) -> Self {
+        match icmp_type {
+            1 => IcmpError::DestinationUnreachable(match icmp_code {
+                0 => Unreachable::Network,
+                1 | 5 | 6 => Unreachable::AdminProhibited,
+                2 => Unreachable::BeyondScope,
+                3 => Unreachable::Host,
+                4 => Unreachable::Port,
+                code => Unreachable::Other(code),
+            }),
+            2 => IcmpError::PacketTooBig {
+                mtu: u32::from_be_bytes(rest),
+            },
+            3 => IcmpError::TimeExceeded(if icmp_code == 1 {
+                TimeExceeded::Reassembly
+            } else {
+                TimeExceeded::Transit
+            }),
+            4 => IcmpError::ParameterProblem {
+                pointer: u32::from_be_bytes(rest),
+            },
+            _ => IcmpError::Other {
+                icmp_type,
+                icmp_code,
+            },
+        }
+    }
+}
+
+/// The wording of iputils `ping`.
+impl fmt::Display for IcmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcmpError::DestinationUnreachable(reason) => match reason {
+                Unreachable::Network => write!(f, "Destination Net Unreachable"),
+                Unreachable::Host => write!(f, "Destination Host Unreachable"),
+                Unreachable::Protocol => write!(f, "Destination Protocol Unreachable"),
+                Unreachable::Port => write!(f, "Destination Port Unreachable"),
+                Unreachable::FragmentationNeeded { next_hop_mtu } => {
+                    write!(f, "Frag needed and DF set (mtu = {})", next_hop_mtu)
+                }
+                Unreachable::SourceRouteFailed => write!(f, "Source Route Failed"),
+                Unreachable::AdminProhibited => write!(f, "Packet filtered"),
+                Unreachable::BeyondScope => write!(f, "Beyond scope of source address"),
+                Unreachable::Other(code) => write!(f, "Dest Unreachable, Bad Code: {}", code),
+            },
+            IcmpError::PacketTooBig { mtu } => write!(f, "Packet too big: mtu={}", mtu),
+            IcmpError::TimeExceeded(TimeExceeded::Transit) => write!(f, "Time to live exceeded"),
+            IcmpError::TimeExceeded(TimeExceeded::Reassembly) => {
+                write!(f, "Frag reassembly time exceeded")
+            }
+            IcmpError::ParameterProblem { pointer } => {
+                write!(f, "Parameter problem: pointer = {}", pointer)
+            }
+            IcmpError::Redirect { gateway } => write!(f, "Redirect (New nexthop: {})", gateway),
+            IcmpError::SourceQuench => write!(f, "Source Quench"),
+            IcmpError::Other {
+                icmp_type,
+                icmp_code,
+            } => write!(f, "Bad ICMP type: {}, code: {}", icmp_type, icmp_code),
+        }
+    }
+}
+
 /// Represents the ICMP reply packet.
 #[derive(Debug)]
 pub enum IcmpPacket {
@@ -13,16 +185,87 @@ pub enum IcmpPacket {
 }
 
 impl IcmpPacket {
+    /// Whether this is an echo reply rather than an ICMP error message.
+    pub fn is_echo_reply(&self) -> bool {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_icmp_type() == IcmpTypes::EchoReply,
+            IcmpPacket::V6(packet) => packet.get_icmpv6_type() == Icmpv6Types::EchoReply,
+        }
+    }
+
+    /// The decoded ICMP error, `None` for echo replies.
+    pub fn get_error(&self) -> Option<IcmpError> {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_error(),
+            IcmpPacket::V6(packet) => packet.get_error(),
+        }
+    }
+
+    /// The address the packet was received from.
+    pub fn get_source(&self) -> IpAddr {
+        match self {
+            IcmpPacket::V4(packet) => IpAddr::V4(packet.get_source()),
+            IcmpPacket::V6(packet) => IpAddr::V6(packet.get_source()),
+        }
+    }
+
+    /// The sequence number of the echo request this packet answers.
+    pub fn get_sequence(&self) -> u16 {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_sequence(),
+            IcmpPacket::V6(packet) => packet.get_sequence(),
+        }
+    }
+
+    /// The payload echoed back, empty for ICMP errors.
+    pub fn get_payload(&self) -> &[u8] {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_payload(),
+            IcmpPacket::V6(packet) => packet.get_payload(),
+        }
+    }
+
+    /// The TOS (ICMPv6: traffic class) byte of the IP header the packet arrived in, `None`
+    /// if the socket could not tell. Compare with the one sent to detect remarking.
+    pub fn get_tos(&self) -> Option<u8> {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_tos(),
+            IcmpPacket::V6(packet) => packet.get_traffic_class(),
+        }
+    }
+
+    /// The DSCP of the packet, the upper 6 bits of [`get_tos`](#method.get_tos).
+    pub fn get_dscp(&self) -> Option<u8> {
+        self.get_tos().map(|tos| tos >> 2)
+    }
+
+    /// The ECN codepoint of the packet, the lower 2 bits of [`get_tos`](#method.get_tos).
+    pub fn get_ecn(&self) -> Option<u8> {
+        self.get_tos().map(|tos| tos & 0b11)
+    }
+
     /// Check reply Icmp packet is corret.
     pub fn check_reply_packet(&self, destination: IpAddr, seq_cnt: u16, identifier: u16) -> bool {
+        self.answers(destination, seq_cnt, Some(identifier))
+    }
+
+    /// Like [`check_reply_packet`](#method.check_reply_packet), not comparing the
+    /// identifier if it is `None`, as when the kernel owns it (`Type::DGRAM` sockets).
+    pub(crate) fn answers(
+        &self,
+        destination: IpAddr,
+        seq_cnt: u16,
+        identifier: Option<u16>,
+    ) -> bool {
         match self {
             IcmpPacket::V4(packet) => {
                 destination.eq(&IpAddr::V4(packet.get_real_dest()))
                     && packet.get_sequence() == seq_cnt
-                    && packet.get_identifier() == identifier
+                    && identifier.map_or(true, |ident| packet.get_identifier() == ident)
             }
             IcmpPacket::V6(packet) => {
-                packet.get_sequence() == seq_cnt && packet.get_identifier() == identifier
+                packet.get_sequence() == seq_cnt
+                    && identifier.map_or(true, |ident| packet.get_identifier() == ident)
             }
         }
     }
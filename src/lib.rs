@@ -1,29 +1,56 @@
 mod client;
 mod config;
+mod dispatch;
 mod error;
 mod icmp;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod monitor;
+mod payload;
 mod ping;
+mod pmtu;
+mod ratelimit;
+mod resolve;
+mod statistics;
+mod stream;
+mod sweep;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod sys;
+mod traceroute;
+mod transport;
+
+pub mod mock;
 
 use std::{net::IpAddr, time::Duration};
 
-pub use client::Client;
+pub use client::{Client, DualStackClient};
 pub use config::{Config, ConfigBuilder};
-pub use error::SurgeError;
-pub use icmp::{icmpv4::Icmpv4Packet, icmpv6::Icmpv6Packet, IcmpPacket};
-pub use ping::Pinger;
+pub use dispatch::DispatchStats;
+pub use error::{SocketOptionError, SurgeError};
+pub use icmp::{
+    icmpv4::Icmpv4Packet, icmpv6::Icmpv6Packet, IcmpError, IcmpPacket, TimeExceeded, Unreachable,
+};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use monitor::{Monitor, MonitorEvent, MonitorPlan, TargetState};
+pub use payload::{PatternParseError, PayloadMismatch, PayloadPattern};
+pub use ping::{PingHandle, Pinger};
+pub use pmtu::{MtuPlan, PathMtu};
+pub use ratelimit::{RateLimit, RateLimitStats};
+pub use resolve::{FamilyPreference, HostPinger, ResolveFuture, ResolvePlan, Resolver};
+pub use statistics::PingStatistics;
+pub use stream::{PingEvent, PingEvents, PingPlan, PingStream};
+pub use sweep::{HostReport, HostStatus, IpRange, RangeParseError, SweepPlan, SweepReport};
+pub use traceroute::{Hop, Probe, Trace, TracePlan, TraceStatus};
+pub use transport::{RecvMeta, SendMeta, SendOptions, Transport, TransportFuture};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ICMP {
+    #[default]
     V4,
     V6,
 }
 
-impl Default for ICMP {
-    fn default() -> Self {
-        ICMP::V4
-    }
-}
-
 /// Shortcut method to ping address.
 /// **NOTE**: This function creates a new internal `Client` on each call,
 /// and so should not be used if making many target. Create a
@@ -31,11 +58,14 @@ impl Default for ICMP {
 ///
 /// # Examples
 ///
-/// ```rust
+/// ```rust,no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
 /// match surge_ping::ping("127.0.0.1".parse()?).await {
 ///     Ok((_packet, duration)) => println!("duration: {:.2?}", duration),
 ///     Err(e) => println!("{:?}", e),
 /// };
+/// # Ok(())
+/// # }
 /// ```
 ///
 /// # Errors
@@ -50,6 +80,6 @@ pub async fn ping(host: IpAddr) -> Result<(IcmpPacket, Duration), SurgeError> {
         IpAddr::V6(_) => Config::builder().kind(ICMP::V6).build(),
     };
     let client = Client::new(&config).await?;
-    let mut pinger = client.pinger(host).await;
+    let pinger = client.pinger(host).await;
     pinger.ping(0).await
 }
@@ -0,0 +1,388 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    dispatch::{Counters, DispatchStats},
+    error::SurgeError,
+    icmp::{IcmpError, TimeExceeded, Unreachable},
+};
+
+/// Upper bounds of the default round trip time buckets, in milliseconds.
+const BUCKETS_MS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+/// Counters and round trip time histograms of the pingers of one or more clients,
+/// rendered in the Prometheus text exposition format. Only available with the `metrics`
+/// feature.
+///
+/// Every [`Client`](struct.Client.html) records into a registry, its own unless one is set
+/// with `ConfigBuilder::metrics`, so clients built from the same `Config` share one:
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use surge_ping::{Config, DualStackClient, Metrics};
+///
+/// let metrics = Metrics::new();
+/// let client = DualStackClient::new(&Config::builder().metrics(metrics.clone()).build()).await?;
+/// let pinger = client.pinger("192.0.2.1".parse()?).await;
+/// let _ = pinger.ping(0).await;
+/// print!("{}", metrics.render());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Targets are kept until they are removed with [`remove`](#method.remove), so sweeps of
+/// large ranges should remove the hosts they are done with.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<Registry>,
+}
+
+struct Registry {
+    buckets: Vec<Duration>,
+    clients: Mutex<Clients>,
+    targets: Mutex<BTreeMap<IpAddr, Arc<Target>>>,
+}
+
+/// The receive tasks counted by a registry.
+#[derive(Default)]
+struct Clients {
+    live: Vec<Arc<Counters>>,
+    /// What the clients dropped since counted, so totals never go down.
+    retired: DispatchStats,
+}
+
+impl Clients {
+    /// Fold the counters no client or receive task holds anymore into `retired`.
+    fn prune(&mut self) {
+        let retired = &mut self.retired;
+        self.live.retain(|counters| {
+            if Arc::strong_count(counters) > 1 {
+                return true;
+            }
+            add(retired, counters.snapshot());
+            false
+        });
+    }
+}
+
+fn add(total: &mut DispatchStats, stats: DispatchStats) {
+    total.received += stats.received;
+    total.delivered += stats.delivered;
+    total.unmatched += stats.unmatched;
+    total.malformed += stats.malformed;
+    total.dropped += stats.dropped;
+}
+
+/// What the pingers of one destination saw.
+#[derive(Debug)]
+pub(crate) struct Target {
+    sent: AtomicU64,
+    received: AtomicU64,
+    timeouts: AtomicU64,
+    corrupt: AtomicU64,
+    abandoned: AtomicU64,
+    errors: Mutex<HashMap<(&'static str, &'static str), u64>>,
+    /// Replies per bucket of `Registry::buckets`, not cumulative, the last one is `+Inf`.
+    rtt: Vec<AtomicU64>,
+    rtt_sum: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::with_buckets(
+            BUCKETS_MS
+                .iter()
+                .map(|ms| Duration::from_millis(*ms))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("buckets", &self.inner.buckets)
+            .field("targets", &self.inner.targets.lock().len())
+            .finish()
+    }
+}
+
+impl Metrics {
+    /// An empty registry with round trip time buckets from 1ms to 5s.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty registry with the given upper bounds of the round trip time buckets.
+    pub fn with_buckets(mut buckets: Vec<Duration>) -> Self {
+        buckets.sort_unstable();
+        buckets.dedup();
+        Metrics {
+            inner: Arc::new(Registry {
+                buckets,
+                clients: Mutex::default(),
+                targets: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Count the messages read by the receive task of a client.
+    pub(crate) fn register(&self, counters: Arc<Counters>) {
+        let mut clients = self.inner.clients.lock();
+        clients.prune();
+        clients.live.push(counters);
+    }
+
+    /// The metrics of `destination`, created on first use.
+    pub(crate) fn target(&self, destination: IpAddr) -> Arc<Target> {
+        self.inner
+            .targets
+            .lock()
+            .entry(destination)
+            .or_insert_with(|| {
+                Arc::new(Target {
+                    sent: AtomicU64::new(0),
+                    received: AtomicU64::new(0),
+                    timeouts: AtomicU64::new(0),
+                    corrupt: AtomicU64::new(0),
+                    abandoned: AtomicU64::new(0),
+                    errors: Mutex::default(),
+                    rtt: (0..=self.inner.buckets.len())
+                        .map(|_| AtomicU64::new(0))
+                        .collect(),
+                    rtt_sum: AtomicU64::new(0),
+                })
+            })
+            .clone()
+    }
+
+    /// Forget the metrics of `destination`, returning whether there were any. Requests
+    /// still outstanding are not counted anymore.
+    pub fn remove(&self, destination: IpAddr) -> bool {
+        self.inner.targets.lock().remove(&destination).is_some()
+    }
+
+    /// The messages read by the clients recording into this registry, added up, including
+    /// the clients dropped since.
+    pub fn dispatch_stats(&self) -> DispatchStats {
+        let mut clients = self.inner.clients.lock();
+        clients.prune();
+        let mut total = clients.retired;
+        for counters in &clients.live {
+            add(&mut total, counters.snapshot());
+        }
+        total
+    }
+
+    /// Everything in the Prometheus text exposition format, ready to be served on a
+    /// `/metrics` endpoint.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out)
+            .expect("writing to a String does not fail");
+        out
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        let stats = self.dispatch_stats();
+        for (name, help, value) in [
+            (
+                "surge_ping_packets_received_total",
+                "Echo replies and ICMP errors read from the socket.",
+                stats.received,
+            ),
+            (
+                "surge_ping_packets_delivered_total",
+                "Received packets handed to the request they answer.",
+                stats.delivered,
+            ),
+            (
+                "surge_ping_packets_unmatched_total",
+                "Received packets answering no outstanding request.",
+                stats.unmatched,
+            ),
+            (
+                "surge_ping_packets_malformed_total",
+                "Received packets that could not be decoded.",
+                stats.malformed,
+            ),
+            (
+                "surge_ping_packets_dropped_total",
+                "Received packets whose request was given up on while handing them over.",
+                stats.dropped,
+            ),
+        ] {
+            header(out, name, help, "counter")?;
+            writeln!(out, "{} {}", name, value)?;
+        }
+
+        let targets: Vec<(IpAddr, Arc<Target>)> = self
+            .inner
+            .targets
+            .lock()
+            .iter()
+            .map(|(addr, target)| (*addr, target.clone()))
+            .collect();
+        for (name, help, counter) in [
+            (
+                "surge_ping_requests_sent_total",
+                "Echo requests sent.",
+                (|target| &target.sent) as fn(&Target) -> &AtomicU64,
+            ),
+            (
+                "surge_ping_replies_received_total",
+                "Echo replies received.",
+                |target| &target.received,
+            ),
+            (
+                "surge_ping_timeouts_total",
+                "Echo requests that timed out.",
+                |target| &target.timeouts,
+            ),
+            (
+                "surge_ping_corrupt_replies_total",
+                "Echo replies whose payload differed from the one sent.",
+                |target| &target.corrupt,
+            ),
+            (
+                "surge_ping_requests_abandoned_total",
+                "Echo requests given up on before their outcome, or whose client stopped.",
+                |target| &target.abandoned,
+            ),
+        ] {
+            header(out, name, help, "counter")?;
+            for (addr, target) in &targets {
+                let value = counter(target).load(Ordering::Relaxed);
+                writeln!(out, "{}{{target=\"{}\"}} {}", name, addr, value)?;
+            }
+        }
+
+        let name = "surge_ping_icmp_errors_total";
+        header(
+            out,
+            name,
+            "ICMP errors received in answer to echo requests.",
+            "counter",
+        )?;
+        for (addr, target) in &targets {
+            let mut errors: Vec<_> = target
+                .errors
+                .lock()
+                .iter()
+                .map(|(labels, count)| (*labels, *count))
+                .collect();
+            errors.sort_unstable();
+            for ((kind, reason), count) in errors {
+                writeln!(
+                    out,
+                    "{}{{target=\"{}\",type=\"{}\",reason=\"{}\"}} {}",
+                    name, addr, kind, reason, count
+                )?;
+            }
+        }
+
+        let name = "surge_ping_rtt_seconds";
+        header(out, name, "Round trip time of echo replies.", "histogram")?;
+        for (addr, target) in &targets {
+            let mut count = 0;
+            for (i, bucket) in target.rtt.iter().enumerate() {
+                count += bucket.load(Ordering::Relaxed);
+                let le = match self.inner.buckets.get(i) {
+                    Some(bound) => bound.as_secs_f64().to_string(),
+                    None => "+Inf".to_string(),
+                };
+                writeln!(
+                    out,
+                    "{}_bucket{{target=\"{}\",le=\"{}\"}} {}",
+                    name, addr, le, count
+                )?;
+            }
+            let sum = Duration::from_nanos(target.rtt_sum.load(Ordering::Relaxed));
+            writeln!(
+                out,
+                "{}_sum{{target=\"{}\"}} {}",
+                name,
+                addr,
+                sum.as_secs_f64()
+            )?;
+            writeln!(out, "{}_count{{target=\"{}\"}} {}", name, addr, count)?;
+        }
+        Ok(())
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) -> fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+impl Target {
+    pub(crate) fn sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Account for the outcome of a request to the target of `metrics`.
+    pub(crate) fn record(&self, metrics: &Metrics, outcome: Result<Duration, &SurgeError>) {
+        match outcome {
+            Ok(rtt) => {
+                self.received.fetch_add(1, Ordering::Relaxed);
+                let bucket = metrics.inner.buckets.partition_point(|bound| bound < &rtt);
+                self.rtt[bucket].fetch_add(1, Ordering::Relaxed);
+                self.rtt_sum
+                    .fetch_add(rtt.as_nanos() as u64, Ordering::Relaxed);
+            }
+            Err(SurgeError::Timeout { .. }) => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(SurgeError::IcmpError { error, .. }) => {
+                *self.errors.lock().entry(labels(error)).or_default() += 1;
+            }
+            Err(SurgeError::CorruptPayload { .. }) => {
+                self.corrupt.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => self.abandoned(),
+        }
+    }
+
+    /// Account for a request dropped before its outcome.
+    pub(crate) fn abandoned(&self) {
+        self.abandoned.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The `type` and `reason` labels of an ICMP error.
+fn labels(error: &IcmpError) -> (&'static str, &'static str) {
+    match error {
+        IcmpError::DestinationUnreachable(reason) => (
+            "destination_unreachable",
+            match reason {
+                Unreachable::Network => "network",
+                Unreachable::Host => "host",
+                Unreachable::Protocol => "protocol",
+                Unreachable::Port => "port",
+                Unreachable::FragmentationNeeded { .. } => "fragmentation_needed",
+                Unreachable::SourceRouteFailed => "source_route_failed",
+                Unreachable::AdminProhibited => "admin_prohibited",
+                Unreachable::BeyondScope => "beyond_scope",
+                Unreachable::Other(_) => "other",
+            },
+        ),
+        IcmpError::PacketTooBig { .. } => ("packet_too_big", ""),
+        IcmpError::TimeExceeded(TimeExceeded::Transit) => ("time_exceeded", "transit"),
+        IcmpError::TimeExceeded(TimeExceeded::Reassembly) => ("time_exceeded", "reassembly"),
+        IcmpError::ParameterProblem { .. } => ("parameter_problem", ""),
+        IcmpError::Redirect { .. } => ("redirect", ""),
+        IcmpError::SourceQuench => ("source_quench", ""),
+        IcmpError::Other { .. } => ("other", ""),
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use parking_lot::Mutex;
+use rand::random;
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
+
+use crate::{error::Result, icmp::IcmpPacket, stream::MIN_INTERVAL, Client, PingPlan};
+
+/// How a [`Monitor`](struct.Monitor.html) probes its targets and decides whether they are
+/// up.
+///
+/// A probe is healthy if it is answered by an echo reply, within `max_rtt` if set, while
+/// the loss over the last probes stays under `max_loss` if set. A target goes up after
+/// `rise` healthy probes in a row and down after `fall` unhealthy ones.
+#[derive(Debug, Clone)]
+pub struct MonitorPlan {
+    interval: Duration,
+    timeout: Duration,
+    rise: usize,
+    fall: usize,
+    max_rtt: Option<Duration>,
+    max_loss: Option<(f64, usize)>,
+    flapping: Option<(usize, Duration)>,
+}
+
+impl Default for MonitorPlan {
+    fn default() -> Self {
+        MonitorPlan {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            rise: 2,
+            fall: 3,
+            max_rtt: None,
+            max_loss: None,
+            flapping: None,
+        }
+    }
+}
+
+impl MonitorPlan {
+    /// Probe every second, up after 2 replies in a row, down after 3 failures in a row.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time between two probes of a target, at least 1ms. (default: 1s)
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// How long to wait for the reply to a probe. (default: 1s)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Healthy probes in a row that bring a target up, at least 1. (default: 2)
+    pub fn rise(mut self, rise: usize) -> Self {
+        self.rise = rise.max(1);
+        self
+    }
+
+    /// Unhealthy probes in a row that bring a target down, at least 1. (default: 3)
+    pub fn fall(mut self, fall: usize) -> Self {
+        self.fall = fall.max(1);
+        self
+    }
+
+    /// Count replies slower than `max_rtt` as unhealthy. (default: none)
+    pub fn max_rtt(mut self, max_rtt: Duration) -> Self {
+        self.max_rtt = Some(max_rtt);
+        self
+    }
+
+    /// Count probes as unhealthy while more than `percent` of the last `window` probes
+    /// went unanswered. (default: none)
+    pub fn max_loss(mut self, percent: f64, window: usize) -> Self {
+        self.max_loss = Some((percent, window.max(1)));
+        self
+    }
+
+    /// Report a target as `TargetState::Flapping` once it went up or down `transitions`
+    /// times within `within`, until it calms down again. (default: off)
+    pub fn flapping(mut self, transitions: usize, within: Duration) -> Self {
+        self.flapping = Some((transitions.max(1), within));
+        self
+    }
+}
+
+/// What a [`Monitor`](struct.Monitor.html) thinks of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TargetState {
+    /// Not enough probes yet to tell.
+    Unknown,
+    Up,
+    Down,
+    /// Going up and down too often to be trusted, see `MonitorPlan::flapping`.
+    Flapping,
+}
+
+impl fmt::Display for TargetState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            TargetState::Unknown => "unknown",
+            TargetState::Up => "up",
+            TargetState::Down => "down",
+            TargetState::Flapping => "flapping",
+        };
+        f.write_str(state)
+    }
+}
+
+/// A change of the state of a target.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct MonitorEvent {
+    pub target: IpAddr,
+    pub previous: TargetState,
+    pub state: TargetState,
+    /// When the probe that caused the change completed.
+    pub at: Instant,
+    /// Round trip time of that probe, if it was answered.
+    pub rtt: Option<Duration>,
+    /// Percentage of the recent probes that went unanswered.
+    pub loss: f64,
+}
+
+/// Turns probe outcomes of one target into state changes.
+#[derive(Debug)]
+struct Tracker {
+    plan: MonitorPlan,
+    state: TargetState,
+    /// The state from the probes alone, without flap detection.
+    settled: TargetState,
+    healthy: usize,
+    unhealthy: usize,
+    /// Whether each of the recent probes was answered, newest last.
+    recent: VecDeque<bool>,
+    transitions: VecDeque<Instant>,
+}
+
+impl Tracker {
+    fn new(plan: MonitorPlan) -> Self {
+        Tracker {
+            plan,
+            state: TargetState::Unknown,
+            settled: TargetState::Unknown,
+            healthy: 0,
+            unhealthy: 0,
+            recent: VecDeque::new(),
+            transitions: VecDeque::new(),
+        }
+    }
+
+    fn loss(&self) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        let lost = self.recent.iter().filter(|answered| !**answered).count();
+        lost as f64 * 100.0 / self.recent.len() as f64
+    }
+
+    /// Account for a probe answered after `rtt`, or not answered, returning the previous
+    /// state if it changed.
+    fn record(&mut self, rtt: Option<Duration>, now: Instant) -> Option<TargetState> {
+        let window = self
+            .plan
+            .max_loss
+            .map_or(self.plan.fall, |(_, window)| window);
+        self.recent.push_back(rtt.is_some());
+        while self.recent.len() > window {
+            self.recent.pop_front();
+        }
+
+        let healthy = match rtt {
+            None => false,
+            Some(rtt) => {
+                self.plan.max_rtt.map_or(true, |max| rtt <= max)
+                    && self
+                        .plan
+                        .max_loss
+                        .map_or(true, |(percent, _)| self.loss() <= percent)
+            }
+        };
+        if healthy {
+            self.healthy += 1;
+            self.unhealthy = 0;
+        } else {
+            self.unhealthy += 1;
+            self.healthy = 0;
+        }
+
+        let settled = if self.healthy >= self.plan.rise {
+            TargetState::Up
+        } else if self.unhealthy >= self.plan.fall {
+            TargetState::Down
+        } else {
+            self.settled
+        };
+        if settled != self.settled {
+            if self.settled != TargetState::Unknown {
+                self.transitions.push_back(now);
+            }
+            self.settled = settled;
+        }
+
+        let mut state = self.settled;
+        if let Some((transitions, within)) = self.plan.flapping {
+            while self
+                .transitions
+                .front()
+                .is_some_and(|at| now.saturating_duration_since(*at) > within)
+            {
+                self.transitions.pop_front();
+            }
+            if self.transitions.len() >= transitions {
+                state = TargetState::Flapping;
+            }
+        }
+        if state == self.state {
+            return None;
+        }
+        Some(std::mem::replace(&mut self.state, state))
+    }
+}
+
+/// Where a `Monitor` sends its events.
+#[derive(Clone)]
+enum Sink {
+    Channel(mpsc::UnboundedSender<MonitorEvent>),
+    Callback(Arc<dyn Fn(&MonitorEvent) + Send + Sync>),
+}
+
+impl Sink {
+    fn emit(&self, event: MonitorEvent) {
+        match self {
+            Sink::Channel(tx) => {
+                let _ = tx.send(event);
+            }
+            Sink::Callback(callback) => callback(&event),
+        }
+    }
+}
+
+/// Probes many targets over one [`Client`](struct.Client.html) and reports when they go
+/// up or down:
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use surge_ping::{Client, Config, Monitor, MonitorPlan};
+///
+/// let client = Client::new(&Config::default()).await?;
+/// let (mut monitor, mut events) = Monitor::new(client, MonitorPlan::new().rise(2).fall(3));
+/// monitor.add("10.0.0.1".parse()?).await;
+/// monitor.add("10.0.0.2".parse()?).await;
+/// while let Some(event) = events.recv().await {
+///     println!("{} is {} (was {})", event.target, event.state, event.previous);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Each target is probed by its own task, started at a random offset within the interval
+/// so probes of many targets are spread out. Dropping the monitor stops them.
+pub struct Monitor {
+    client: Client,
+    plan: MonitorPlan,
+    sink: Sink,
+    /// The state of each target, with the generation of the task probing it.
+    states: Arc<Mutex<HashMap<IpAddr, (u64, TargetState)>>>,
+    tasks: HashMap<IpAddr, JoinHandle<()>>,
+    generation: u64,
+}
+
+impl Monitor {
+    /// A monitor sending its events to the returned channel.
+    pub fn new(
+        client: Client,
+        plan: MonitorPlan,
+    ) -> (Monitor, mpsc::UnboundedReceiver<MonitorEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self::with_sink(client, plan, Sink::Channel(tx)), rx)
+    }
+
+    /// A monitor calling `callback` with each event, from the task of the target.
+    pub fn with_callback<F>(client: Client, plan: MonitorPlan, callback: F) -> Monitor
+    where
+        F: Fn(&MonitorEvent) + Send + Sync + 'static,
+    {
+        Self::with_sink(client, plan, Sink::Callback(Arc::new(callback)))
+    }
+
+    fn with_sink(client: Client, plan: MonitorPlan, sink: Sink) -> Monitor {
+        Monitor {
+            client,
+            plan,
+            sink,
+            states: Arc::default(),
+            tasks: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// Start probing `target`, its state is `TargetState::Unknown` until enough probes
+    /// completed. Adding a target again restarts it.
+    pub async fn add(&mut self, target: IpAddr) {
+        self.remove(target);
+        let mut pinger = self.client.pinger(target).await;
+        pinger.timeout(self.plan.timeout);
+        let plan = self.plan.clone();
+        let sink = self.sink.clone();
+        let states = self.states.clone();
+        self.generation += 1;
+        let generation = self.generation;
+        states
+            .lock()
+            .insert(target, (generation, TargetState::Unknown));
+
+        let task = tokio::spawn(async move {
+            sleep(plan.interval.mul_f64(random::<f64>())).await;
+            let mut tracker = Tracker::new(plan.clone());
+            let mut probes = pinger.stream(PingPlan::new().interval(plan.interval));
+            while let Some((_, result)) = probes.next().await {
+                let now = Instant::now();
+                let rtt = rtt(&result);
+                if let Some(previous) = tracker.record(rtt, now) {
+                    // An aborted task may still complete a probe, it must not bring back
+                    // a target that was removed or restarted meanwhile.
+                    match states.lock().get_mut(&target) {
+                        Some((current, state)) if *current == generation => *state = tracker.state,
+                        _ => return,
+                    }
+                    sink.emit(MonitorEvent {
+                        target,
+                        previous,
+                        state: tracker.state,
+                        at: now,
+                        rtt,
+                        loss: tracker.loss(),
+                    });
+                }
+            }
+        });
+        self.tasks.insert(target, task);
+    }
+
+    /// Stop probing `target`, returning whether it was monitored.
+    pub fn remove(&mut self, target: IpAddr) -> bool {
+        let task = self.tasks.remove(&target);
+        if let Some(task) = &task {
+            task.abort();
+        }
+        self.states.lock().remove(&target);
+        task.is_some()
+    }
+
+    /// The current state of `target`, `None` if it is not monitored.
+    pub fn state(&self, target: IpAddr) -> Option<TargetState> {
+        self.states.lock().get(&target).map(|(_, state)| *state)
+    }
+
+    /// The current state of every target.
+    pub fn states(&self) -> HashMap<IpAddr, TargetState> {
+        self.states
+            .lock()
+            .iter()
+            .map(|(target, (_, state))| (*target, *state))
+            .collect()
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+fn rtt(result: &Result<(IcmpPacket, Duration)>) -> Option<Duration> {
+    result.as_ref().ok().map(|(_, rtt)| *rtt)
+}
@@ -0,0 +1,124 @@
+use std::{fmt, str::FromStr};
+
+use rand::random;
+use thiserror::Error;
+
+/// Most offsets a [`PayloadMismatch`](struct.PayloadMismatch.html) lists.
+const MAX_OFFSETS: usize = 32;
+
+/// How the payload of echo requests is filled, like the `-p` option of `ping`.
+///
+/// The first 2 bytes carry the identifier of the `Pinger` unless its `payload_token` is
+/// turned off, the pattern starts after them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum PayloadPattern {
+    /// All zeros.
+    #[default]
+    Zeros,
+    /// The given bytes over and over, e.g. `ff00` for alternating bits.
+    Repeat(Vec<u8>),
+    /// The offset of each byte from the start of the pattern, wrapping around after 255.
+    Incrementing,
+    /// Random bytes, new for each request.
+    Random,
+}
+
+/// Why a string is not a [`PayloadPattern`](enum.PayloadPattern.html).
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid payload pattern {0:?}")]
+pub struct PatternParseError(String);
+
+/// Parses `zeros`, `incrementing`, `random`, or up to 16 bytes of hex digits to repeat
+/// like `ping -p`.
+impl FromStr for PayloadPattern {
+    type Err = PatternParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zeros" => return Ok(PayloadPattern::Zeros),
+            "incrementing" => return Ok(PayloadPattern::Incrementing),
+            "random" => return Ok(PayloadPattern::Random),
+            _ => {}
+        }
+        let invalid = || PatternParseError(s.to_string());
+        if s.is_empty() || s.len() % 2 != 0 || s.len() > 32 {
+            return Err(invalid());
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| {
+                s.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<Vec<u8>, _>>()
+            .map(PayloadPattern::Repeat)
+    }
+}
+
+impl PayloadPattern {
+    /// A payload of `size` bytes starting with `token`, followed by the pattern.
+    pub(crate) fn fill(&self, size: usize, token: &[u8]) -> Vec<u8> {
+        let len = token.len().min(size);
+        let rest = size - len;
+        let mut payload = token[..len].to_vec();
+        match self {
+            PayloadPattern::Repeat(bytes) if !bytes.is_empty() => {
+                payload.extend(bytes.iter().copied().cycle().take(rest))
+            }
+            PayloadPattern::Zeros | PayloadPattern::Repeat(_) => payload.resize(size, 0),
+            PayloadPattern::Incrementing => payload.extend((0..rest).map(|i| i as u8)),
+            PayloadPattern::Random => payload.extend((0..rest).map(|_| random::<u8>())),
+        }
+        payload
+    }
+}
+
+/// How an echoed payload differs from the one sent, see `Pinger::verify_payload`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PayloadMismatch {
+    /// Length of the payload sent.
+    pub sent: usize,
+    /// Length of the payload echoed back.
+    pub received: usize,
+    /// Number of bytes that differ, bytes missing from a truncated payload included.
+    pub differing: usize,
+    /// Offsets of the first differing bytes, at most 32.
+    pub offsets: Vec<usize>,
+}
+
+impl PayloadMismatch {
+    /// Compare an echoed payload with the one sent, `None` if they match.
+    pub(crate) fn compare(sent: &[u8], received: &[u8]) -> Option<PayloadMismatch> {
+        if sent == received {
+            return None;
+        }
+        let mut differing =
+            (0..sent.len().max(received.len())).filter(|i| sent.get(*i) != received.get(*i));
+        let offsets: Vec<usize> = differing.by_ref().take(MAX_OFFSETS).collect();
+        Some(PayloadMismatch {
+            sent: sent.len(),
+            received: received.len(),
+            differing: offsets.len() + differing.count(),
+            offsets,
+        })
+    }
+}
+
+impl fmt::Display for PayloadMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.sent != self.received {
+            write!(f, "{} bytes sent, {} echoed, ", self.sent, self.received)?;
+        }
+        write!(f, "{} bytes differ at offset", self.differing)?;
+        for (i, offset) in self.offsets.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, offset)?;
+        }
+        if self.differing > self.offsets.len() {
+            write!(f, ", ...")?;
+        }
+        Ok(())
+    }
+}
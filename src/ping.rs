@@ -1,44 +1,383 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    future::Future,
+    io,
     net::{IpAddr, SocketAddr},
+    pin::Pin,
     sync::Arc,
+    task::{Context, Poll},
     time::{Duration, Instant},
 };
 
 use parking_lot::Mutex;
 use rand::random;
+use socket2::Type;
 use tokio::{
-    sync::{broadcast, mpsc, Mutex as TokioMutex},
-    task,
-    time::timeout,
+    sync::{mpsc, oneshot},
+    time::{sleep, Sleep},
 };
-use tracing::{error, warn};
-use uuid::Uuid;
 
-use crate::client::{AsyncSocket, Message};
+use crate::dispatch::{Mapping, PingerKey};
 use crate::error::{Result, SurgeError};
 use crate::icmp::{icmpv4, icmpv6, IcmpPacket};
+#[cfg(feature = "metrics")]
+use crate::metrics::{Metrics, Target};
+use crate::payload::{PayloadMismatch, PayloadPattern};
+use crate::pmtu::{self, MtuPlan, PathMtu};
+use crate::ratelimit::RateLimiter;
+use crate::stream::{PingEvent, PingEvents, PingPlan, PingStream};
+use crate::traceroute::{self, Trace, TracePlan};
+use crate::transport::{SendOptions, Transport};
 
 type Token = (u16, u16);
 
+/// A reply handed to its request: the packet, when it was received and whether a request
+/// sent later was answered first.
+type Reply = (IcmpPacket, Instant, bool);
+
+/// How many answered or timed out requests a pinger keeps while it has a `PingEvents`
+/// stream, to recognize duplicate and late replies.
+const HISTORY: usize = 128;
+
+/// How many duplicate and late replies wait for a `PingEvents` stream to take them, more
+/// are not reported.
+const EVENTS: usize = 64;
+
+#[derive(Debug)]
+struct Waiter {
+    destination: IpAddr,
+    sent: Instant,
+    timeout: Duration,
+    /// Position of the request in send order.
+    order: u64,
+    tx: oneshot::Sender<Reply>,
+}
+
+/// A request that was answered or timed out, remembered to classify further replies.
+#[derive(Debug)]
+struct Completed {
+    destination: IpAddr,
+    sent: Instant,
+    timeout: Duration,
+    order: u64,
+    answered: bool,
+}
+
+#[derive(Debug, Default)]
+struct Requests {
+    waiting: HashMap<Token, Waiter>,
+    /// Only kept while `events` is set.
+    completed: HashMap<Token, Completed>,
+    history: VecDeque<(Token, u64)>,
+    sent: u64,
+    /// The latest request in send order that was answered.
+    latest_answered: Option<u64>,
+    events: Option<mpsc::Sender<PingEvent>>,
+}
+
+impl Requests {
+    /// The request `packet` answers among the tokens of `keys`, by identifier and
+    /// sequence number or by sequence number alone.
+    fn find<'a>(
+        mut keys: impl Iterator<Item = &'a Token>,
+        (ident, seq_cnt): Token,
+        by_ident: bool,
+    ) -> Option<Token> {
+        if by_ident {
+            keys.find(|token| **token == (ident, seq_cnt)).copied()
+        } else {
+            keys.find(|(_, seq)| *seq == seq_cnt).copied()
+        }
+    }
+
+    fn complete(&mut self, token: Token, waiter: &Waiter, answered: bool) {
+        if self.events.is_none() {
+            return;
+        }
+        self.completed.insert(
+            token,
+            Completed {
+                destination: waiter.destination,
+                sent: waiter.sent,
+                timeout: waiter.timeout,
+                order: waiter.order,
+                answered,
+            },
+        );
+        self.history.push_back((token, waiter.order));
+        while self.history.len() > HISTORY {
+            if let Some((token, order)) = self.history.pop_front() {
+                if let Entry::Occupied(entry) = self.completed.entry(token) {
+                    if entry.get().order == order {
+                        entry.remove();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Report a duplicate or late reply to the `PingEvents` stream, if there is one.
+    fn report(&mut self, event: PingEvent) {
+        if let Some(events) = &self.events {
+            if let Err(mpsc::error::TrySendError::Closed(_)) = events.try_send(event) {
+                self.events = None;
+                self.completed.clear();
+                self.history.clear();
+            }
+        }
+    }
+}
+
+/// What became of a packet handed to a `Cache`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    /// Handed to the request it answers.
+    Delivered,
+    /// It answers a request given up on while it was handed over.
+    Dropped,
+    /// A duplicate or late reply to a completed request.
+    Reported,
+}
+
+/// The outstanding requests of one `Pinger`, shared with the receive task of the `Client`.
 #[derive(Debug, Clone)]
-struct Cache {
-    inner: Arc<Mutex<HashMap<Token, Instant>>>,
+pub(crate) struct Cache {
+    inner: Arc<Mutex<Requests>>,
 }
 
 impl Cache {
     fn new() -> Cache {
         Cache {
-            inner: Arc::new(Mutex::new(HashMap::new())),
+            inner: Arc::default(),
+        }
+    }
+
+    fn insert(
+        &self,
+        ident: u16,
+        seq_cnt: u16,
+        destination: IpAddr,
+        timeout: Duration,
+    ) -> oneshot::Receiver<Reply> {
+        let (tx, rx) = oneshot::channel();
+        let mut inner = self.inner.lock();
+        let waiter = Waiter {
+            destination,
+            sent: Instant::now(),
+            timeout,
+            order: inner.sent,
+            tx,
+        };
+        inner.sent += 1;
+        inner.completed.remove(&(ident, seq_cnt));
+        inner.waiting.insert((ident, seq_cnt), waiter);
+        rx
+    }
+
+    /// Correct the send time of a request once it was sent, e.g. to the transmit timestamp
+    /// of the kernel.
+    fn sent_at(&self, ident: u16, seq_cnt: u16, sent: Instant) {
+        if let Some(waiter) = self.inner.lock().waiting.get_mut(&(ident, seq_cnt)) {
+            waiter.sent = sent;
+        }
+    }
+
+    /// Give up on the request, remembering it so a late reply can be reported.
+    fn time_out(&self, ident: u16, seq_cnt: u16) {
+        let mut inner = self.inner.lock();
+        if let Some(waiter) = inner.waiting.remove(&(ident, seq_cnt)) {
+            inner.complete((ident, seq_cnt), &waiter, false);
+        }
+    }
+
+    /// Remove the request if nobody waits for its reply anymore.
+    fn abandon(&self, ident: u16, seq_cnt: u16) {
+        if let Entry::Occupied(entry) = self.inner.lock().waiting.entry((ident, seq_cnt)) {
+            if entry.get().tx.is_closed() {
+                entry.remove();
+            }
+        }
+    }
+
+    /// Start reporting duplicate and late replies to the returned receiver, instead of
+    /// any previous one.
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<PingEvent> {
+        let (tx, rx) = mpsc::channel(EVENTS);
+        let mut inner = self.inner.lock();
+        inner.events = Some(tx);
+        inner.completed.clear();
+        inner.history.clear();
+        rx
+    }
+
+    pub(crate) fn same(&self, other: &Cache) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Hand a received packet to the request it answers, giving it back if there is none.
+    ///
+    /// With `by_ident` false the identifier of the packet is not trusted and the request
+    /// is found by its sequence number alone.
+    pub(crate) fn dispatch(
+        &self,
+        packet: IcmpPacket,
+        received: Instant,
+        by_ident: bool,
+    ) -> std::result::Result<Delivery, IcmpPacket> {
+        let (ident, seq_cnt) = match &packet {
+            IcmpPacket::V4(packet) => (packet.get_identifier(), packet.get_sequence()),
+            IcmpPacket::V6(packet) => (packet.get_identifier(), packet.get_sequence()),
+        };
+        let trusted = by_ident.then_some(ident);
+
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let token = Requests::find(inner.waiting.keys(), (ident, seq_cnt), by_ident);
+        if let Some(token) = token {
+            if let Entry::Occupied(entry) = inner.waiting.entry(token) {
+                if packet.answers(entry.get().destination, seq_cnt, trusted) {
+                    let waiter = entry.remove();
+                    let reordered = inner
+                        .latest_answered
+                        .is_some_and(|latest| waiter.order < latest);
+                    inner.latest_answered = inner.latest_answered.max(Some(waiter.order));
+                    inner.complete(token, &waiter, true);
+                    // A closed receiver is a request given up on, not a reply to send
+                    // elsewhere.
+                    return Ok(match waiter.tx.send((packet, received, reordered)) {
+                        Ok(()) => Delivery::Delivered,
+                        Err(_) => Delivery::Dropped,
+                    });
+                }
+            }
+        }
+
+        if packet.get_error().is_some() {
+            return Err(packet);
+        }
+        let token = Requests::find(inner.completed.keys(), (ident, seq_cnt), by_ident);
+        if let Some(token) = token {
+            if let Some(completed) = inner.completed.get_mut(&token) {
+                if packet.answers(completed.destination, seq_cnt, trusted) {
+                    let rtt = received.saturating_duration_since(completed.sent);
+                    let event = if completed.answered {
+                        PingEvent::Duplicate { seq: seq_cnt, rtt }
+                    } else {
+                        PingEvent::Late {
+                            seq: seq_cnt,
+                            rtt,
+                            late_by: rtt.saturating_sub(completed.timeout),
+                        }
+                    };
+                    completed.answered = true;
+                    inner.report(event);
+                    return Ok(Delivery::Reported);
+                }
+            }
+        }
+        Err(packet)
+    }
+}
+
+/// A request sent by [`Pinger::send`](struct.Pinger.html#method.send), resolving to its
+/// echo reply, to `SurgeError::IcmpError` if a router answered with an ICMP error, or to
+/// `SurgeError::Timeout` once the timeout of the `Pinger` has elapsed.
+///
+/// Dropping the handle abandons the request.
+#[derive(Debug)]
+pub struct PingHandle {
+    ident: u16,
+    seq_cnt: u16,
+    sent: Instant,
+    rx: oneshot::Receiver<Reply>,
+    deadline: Pin<Box<Sleep>>,
+    cache: Cache,
+    reordered: bool,
+    /// The payload sent, if the echoed one is to be verified.
+    expected: Option<Vec<u8>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<(Metrics, Arc<Target>)>,
+}
+
+impl PingHandle {
+    /// The sequence number of the request.
+    pub fn sequence(&self) -> u16 {
+        self.seq_cnt
+    }
+
+    /// Wait for the outcome along with whether a request sent later was answered first.
+    pub(crate) async fn ordered(mut self) -> (Result<(IcmpPacket, Duration)>, bool) {
+        let outcome = (&mut self).await;
+        (outcome, self.reordered)
+    }
+}
+
+impl Future for PingHandle {
+    type Output = Result<(IcmpPacket, Duration)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let outcome = self.as_mut().outcome(cx);
+        #[cfg(feature = "metrics")]
+        if let Poll::Ready(outcome) = &outcome {
+            if let Some((metrics, target)) = self.metrics.take() {
+                target.record(&metrics, outcome.as_ref().map(|(_, rtt)| *rtt));
+            }
         }
+        outcome
     }
+}
 
-    fn insert(&self, ident: u16, seq_cnt: u16, time: Instant) {
-        self.inner.lock().insert((ident, seq_cnt), time);
+impl PingHandle {
+    fn outcome(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(IcmpPacket, Duration)>> {
+        if let Poll::Ready(reply) = Pin::new(&mut self.rx).poll(cx) {
+            return Poll::Ready(match reply {
+                Ok((packet, received, reordered)) => {
+                    self.reordered = reordered;
+                    match packet.get_error() {
+                        Some(error) => Err(SurgeError::IcmpError {
+                            seq: self.seq_cnt,
+                            from: packet.get_source(),
+                            error,
+                            rtt: received.saturating_duration_since(self.sent),
+                        }),
+                        None => {
+                            let rtt = received.saturating_duration_since(self.sent);
+                            let mismatch = self.expected.as_ref().and_then(|expected| {
+                                PayloadMismatch::compare(expected, packet.get_payload())
+                            });
+                            match mismatch {
+                                Some(mismatch) => Err(SurgeError::CorruptPayload {
+                                    seq: self.seq_cnt,
+                                    rtt,
+                                    mismatch,
+                                }),
+                                None => Ok((packet, rtt)),
+                            }
+                        }
+                    }
+                }
+                Err(_) => Err(SurgeError::NetworkError),
+            });
+        }
+        if self.deadline.as_mut().poll(cx).is_ready() {
+            self.cache.time_out(self.ident, self.seq_cnt);
+            return Poll::Ready(Err(SurgeError::Timeout { seq: self.seq_cnt }));
+        }
+        Poll::Pending
     }
+}
 
-    fn remove(&self, ident: u16, seq_cnt: u16) -> Option<Instant> {
-        self.inner.lock().remove(&(ident, seq_cnt))
+impl Drop for PingHandle {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.cache.abandon(self.ident, self.seq_cnt);
+        #[cfg(feature = "metrics")]
+        if let Some((_, target)) = self.metrics.take() {
+            target.abandoned();
+        }
     }
 }
 
@@ -48,53 +387,150 @@ pub struct Pinger {
     pub ident: u16,
     pub size: usize,
     timeout: Duration,
-    socket: AsyncSocket,
-    rx: mpsc::Receiver<Message>,
+    ttl: Option<u8>,
+    tos: Option<u8>,
+    pattern: PayloadPattern,
+    token: bool,
+    verify: bool,
+    socket: Arc<dyn Transport>,
+    limiter: Option<Arc<RateLimiter>>,
     cache: Cache,
-    key: Uuid,
-    clear_tx: broadcast::Sender<()>,
+    mapping: Mapping,
+    registered: Mutex<PingerKey>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Option<Metrics>,
 }
 
 impl Drop for Pinger {
     fn drop(&mut self) {
-        if self.clear_tx.send(()).is_err() {
-            warn!("Clear Pinger cache failed");
-        }
+        let key = *self.registered.lock();
+        self.mapping.remove(key, &self.cache);
     }
 }
 
 impl Pinger {
+    /// A pinger registered in `mapping` under `host` and a random identifier no other
+    /// pinger uses for `host`.
     pub(crate) fn new(
         host: IpAddr,
-        socket: AsyncSocket,
-        rx: mpsc::Receiver<Message>,
-        key: Uuid,
-        mapping: Arc<TokioMutex<HashMap<Uuid, mpsc::Sender<Message>>>>,
+        socket: Arc<dyn Transport>,
+        limiter: Option<Arc<RateLimiter>>,
+        mapping: Mapping,
     ) -> Pinger {
-        let (clear_tx, _) = broadcast::channel(1);
-        task::spawn(clear_mapping_key(key, mapping, clear_tx.subscribe()));
+        let cache = Cache::new();
+        let ident = loop {
+            let ident = random();
+            if mapping.try_insert((host, ident), cache.clone()) {
+                break ident;
+            }
+        };
         Pinger {
             destination: host,
-            ident: random(),
+            ident,
             size: 56,
             timeout: Duration::from_secs(2),
+            ttl: None,
+            tos: None,
+            pattern: PayloadPattern::default(),
+            token: true,
+            verify: false,
             socket,
-            rx,
-            cache: Cache::new(),
-            key,
-            clear_tx,
+            limiter,
+            cache,
+            mapping,
+            registered: Mutex::new((host, ident)),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Make sure replies for the current `destination` and `ident` find this pinger, they
+    /// are public fields and may have changed since the last request.
+    ///
+    /// Fails with `ErrorKind::AddrInUse` if another pinger of the `Client` uses them.
+    fn register(&self) -> Result<()> {
+        let key = (self.destination, self.ident);
+        let mut registered = self.registered.lock();
+        if *registered == key {
+            return Ok(());
+        }
+        if !self.mapping.try_insert(key, self.cache.clone()) {
+            return Err(SurgeError::IOError(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "identifier {} is used by another pinger for {}",
+                    self.ident, self.destination
+                ),
+            )));
         }
+        self.mapping.remove(*registered, &self.cache);
+        *registered = key;
+        Ok(())
+    }
+
+    /// Take over the settings of `other`, except for the destination and identifier.
+    pub(crate) fn inherit(&mut self, other: &Pinger) {
+        self.size = other.size;
+        self.timeout = other.timeout;
+        self.ttl = other.ttl;
+        self.tos = other.tos;
+        self.pattern = other.pattern.clone();
+        self.token = other.token;
+        self.verify = other.verify;
     }
 
-    /// Set the identification of ICMP.
+    /// Set the identification of ICMP. (default: random, unique among the pingers of the
+    /// `Client` for the same destination)
+    ///
+    /// Replies are matched to their `Pinger` by destination and identifier, so two pingers
+    /// for the same destination cannot share one: requests fail with
+    /// `ErrorKind::AddrInUse` while another pinger uses it. With `Type::DGRAM` sockets, where
+    /// the kernel assigns the identifier on the wire, a copy carried at the start of the
+    /// payload is used instead when the payload has room for it, see
+    /// [`payload_token`](#method.payload_token).
     pub fn ident(&mut self, val: u16) -> &mut Pinger {
         self.ident = val;
         self
     }
 
-    /// Set the packet payload size, minimal is 16. (default: 56)
+    /// Set the packet payload size, it can be 0 for bare 8-byte ICMP messages.
+    /// (default: 56)
+    ///
+    /// The first 2 bytes of the payload carry the identifier, which tells pingers for the
+    /// same destination apart when the kernel or a middlebox rewrites the identifier,
+    /// unless [`payload_token`](#method.payload_token) is turned off.
     pub fn size(&mut self, size: usize) -> &mut Pinger {
-        self.size = if size < 16 { 16 } else { size };
+        self.size = size;
+        self
+    }
+
+    /// Fill the payload of the requests with `pattern`, like the `-p` option of `ping`.
+    /// (default: zeros)
+    ///
+    /// The pattern starts after the copy of the identifier in the first 2 bytes, or at the
+    /// start of the payload without [`payload_token`](#method.payload_token).
+    pub fn pattern(&mut self, pattern: PayloadPattern) -> &mut Pinger {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Carry a copy of the identifier in the first 2 bytes of the payload. (default: true)
+    ///
+    /// Turn it off to send the `pattern` from the first byte on. Replies are then matched
+    /// by their identifier alone, which a middlebox rewriting it breaks. `Type::DGRAM`
+    /// sockets always carry the copy, the kernel rewrites the identifier on the wire.
+    pub fn payload_token(&mut self, enable: bool) -> &mut Pinger {
+        self.token = enable;
+        self
+    }
+
+    /// Check that replies echo the payload byte for byte, failing those that do not with
+    /// `SurgeError::CorruptPayload`, which tells the offsets of the bytes that differ.
+    /// (default: false)
+    ///
+    /// Use it with a `pattern` other than zeros to catch links and NICs that mangle data.
+    pub fn verify_payload(&mut self, verify: bool) -> &mut Pinger {
+        self.verify = verify;
         self
     }
 
@@ -104,76 +540,241 @@ impl Pinger {
         self
     }
 
-    async fn recv_reply(&mut self, seq_cnt: u16) -> Result<(IcmpPacket, Duration)> {
-        loop {
-            let message = self.rx.recv().await.ok_or(SurgeError::NetworkError)?;
-            let packet = match self.destination {
-                IpAddr::V4(_) => icmpv4::Icmpv4Packet::decode(&message.packet).map(IcmpPacket::V4),
-                IpAddr::V6(a) => {
-                    icmpv6::Icmpv6Packet::decode(&message.packet, a).map(IcmpPacket::V6)
-                }
-            };
-            match packet {
-                Ok(packet) => {
-                    if packet.check_reply_packet(self.destination, seq_cnt, self.ident) {
-                        if let Some(ins) = self.cache.remove(self.ident, seq_cnt) {
-                            return Ok((packet, message.when - ins));
-                        }
-                    }
-                }
-                Err(SurgeError::EchoRequestPacket) => continue,
-                Err(e) => return Err(e),
-            }
-        }
+    /// Set the TTL (or hop limit) of the requests of this `Pinger` only. It is sent along
+    /// with each packet, so pingers sharing the `Client` keep their own. (default: the
+    /// one of the socket, see `ConfigBuilder::ttl`)
+    ///
+    /// Only Linux sends it as a control message. Elsewhere it is set on the socket for
+    /// the time of the send, which holds back the other requests of the `Client`.
+    pub fn ttl(&mut self, ttl: u8) -> &mut Pinger {
+        self.ttl = Some(ttl);
+        self
     }
 
-    /// Send Ping request with sequence number.
-    pub async fn ping(&mut self, seq_cnt: u16) -> Result<(IcmpPacket, Duration)> {
-        let sender = self.socket.clone();
-        let mut packet = match self.destination {
-            IpAddr::V4(_) => icmpv4::make_icmpv4_echo_packet(
-                self.ident,
-                seq_cnt,
-                self.size,
-                self.key.as_bytes(),
-            )?,
-            IpAddr::V6(_) => icmpv6::make_icmpv6_echo_packet(
-                self.ident,
-                seq_cnt,
-                self.size,
-                self.key.as_bytes(),
-            )?,
+    /// Set the TOS (ICMPv6: traffic class) byte of the requests of this `Pinger` only, DSCP
+    /// in the upper 6 bits and ECN in the lower 2. It is sent along with each packet like
+    /// the TTL. (default: the one of the socket, see `ConfigBuilder::tos`)
+    ///
+    /// Replies report the TOS they arrived with in `IcmpPacket::get_tos`.
+    ///
+    /// Outside of Linux it is set on the socket for the time of the send like the TTL,
+    /// and requests to IPv6 hosts fail with `ErrorKind::Unsupported`.
+    pub fn tos(&mut self, tos: u8) -> &mut Pinger {
+        self.tos = Some(tos);
+        self
+    }
+
+    /// Send an echo request with sequence number without waiting for the reply.
+    ///
+    /// The returned [`PingHandle`](struct.PingHandle.html) resolves to the reply, so any
+    /// number of sequences can be outstanding at once:
+    ///
+    /// ```rust,no_run
+    /// # async fn run(pinger: surge_ping::Pinger) -> Result<(), surge_ping::SurgeError> {
+    /// let first = pinger.send(0).await?;
+    /// let second = pinger.send(1).await?;
+    /// let (first, second) = tokio::join!(first, second);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send(&self, seq_cnt: u16) -> Result<PingHandle> {
+        self.send_with(seq_cnt, SendOptions::default()).await
+    }
+
+    /// Like [`send`](#method.send), with `options` overriding the settings of the `Pinger`
+    /// for this request only.
+    ///
+    /// ```rust,no_run
+    /// # async fn run(pinger: surge_ping::Pinger) -> Result<(), surge_ping::SurgeError> {
+    /// use surge_ping::SendOptions;
+    ///
+    /// let mut options = SendOptions::new();
+    /// options.hop_limit = Some(3);
+    /// let reply = pinger.send_with(0, options).await?.await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_with(&self, seq_cnt: u16, mut options: SendOptions) -> Result<PingHandle> {
+        options.hop_limit = options.hop_limit.or(self.ttl);
+        options.tos = options.tos.or(self.tos);
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(self.destination).await;
+        }
+        self.register()?;
+        let token = if self.token || self.socket.sock_type() == Type::DGRAM {
+            &self.ident.to_be_bytes()[..]
+        } else {
+            &[]
+        };
+        let payload = self.pattern.fill(self.size, token);
+        let packet = match self.destination {
+            IpAddr::V4(_) => icmpv4::make_icmpv4_echo_packet(self.ident, seq_cnt, &payload)?,
+            IpAddr::V6(_) => icmpv6::make_icmpv6_echo_packet(self.ident, seq_cnt, &payload)?,
         };
-        // let mut packet = EchoRequest::new(self.host, self.ident, seq_cnt, self.size).encode()?;
         let sock_addr = SocketAddr::new(self.destination, 0);
-        let ident = self.ident;
-        let cache = self.cache.clone();
-        task::spawn(async move {
-            if let Err(e) = sender.send_to(&mut packet, &sock_addr).await {
-                error!("socket send packet error: {}", e)
-            }
-            cache.insert(ident, seq_cnt, Instant::now());
-        });
-
-        match timeout(self.timeout, self.recv_reply(seq_cnt)).await {
-            Ok(reply) => reply.map_err(|err| {
-                self.cache.remove(ident, seq_cnt);
-                err
-            }),
-            Err(_) => {
-                self.cache.remove(ident, seq_cnt);
-                Err(SurgeError::Timeout { seq: seq_cnt })
-            }
+        let rx = self
+            .cache
+            .insert(self.ident, seq_cnt, self.destination, self.timeout);
+        let mut handle = PingHandle {
+            ident: self.ident,
+            seq_cnt,
+            sent: Instant::now(),
+            rx,
+            deadline: Box::pin(sleep(self.timeout)),
+            cache: self.cache.clone(),
+            reordered: false,
+            expected: self.verify.then_some(payload),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        };
+        let meta = self.socket.send_msg(&packet, &sock_addr, options).await?;
+        // The request is in the cache before it is sent so a fast reply finds it, but the
+        // RTT starts once it left rather than before the send call and any batch queueing.
+        let sent = meta.timestamp.unwrap_or_else(Instant::now);
+        handle.sent = sent;
+        self.cache.sent_at(self.ident, seq_cnt, sent);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            let target = metrics.target(self.destination);
+            target.sent();
+            handle.metrics = Some((metrics.clone(), target));
         }
+        Ok(handle)
+    }
+
+    /// Send Ping request with sequence number and wait for the reply.
+    pub async fn ping(&self, seq_cnt: u16) -> Result<(IcmpPacket, Duration)> {
+        self.send(seq_cnt).await?.await
+    }
+
+    /// Like [`ping`](#method.ping), along with whether a request sent later was answered
+    /// first.
+    pub(crate) async fn ping_ordered(
+        &self,
+        seq_cnt: u16,
+    ) -> (Result<(IcmpPacket, Duration)>, bool) {
+        match self.send(seq_cnt).await {
+            Ok(handle) => handle.ordered().await,
+            Err(e) => (Err(e), false),
+        }
+    }
+
+    /// Like [`ping`](#method.ping), with `options` overriding the settings of the `Pinger`
+    /// for this request only, with the platform limits of [`ttl`](#method.ttl) and
+    /// [`tos`](#method.tos).
+    pub async fn ping_with(
+        &self,
+        seq_cnt: u16,
+        options: SendOptions,
+    ) -> Result<(IcmpPacket, Duration)> {
+        self.send_with(seq_cnt, options).await?.await
+    }
+
+    /// Keep pinging according to `plan`, yielding the outcome of every sequence.
+    ///
+    /// ```rust,no_run
+    /// # async fn run(pinger: surge_ping::Pinger) {
+    /// use std::time::Duration;
+    ///
+    /// use futures::StreamExt;
+    /// use surge_ping::PingPlan;
+    ///
+    /// let plan = PingPlan::new().interval(Duration::from_millis(200)).count(10);
+    /// let mut stream = pinger.stream(plan);
+    /// while let Some((seq, outcome)) = stream.next().await {
+    ///     println!("icmp_seq={} {:?}", seq, outcome);
+    /// }
+    /// # }
+    /// ```
+    pub fn stream(&self, plan: PingPlan) -> PingStream<'_> {
+        PingStream::new(self, plan)
+    }
+
+    /// Like [`stream`](#method.stream), also reporting duplicate replies, replies that
+    /// came after the timeout and replies that overtook an earlier request, like the
+    /// `DUP!` of `ping`:
+    ///
+    /// ```rust,no_run
+    /// # async fn run(pinger: surge_ping::Pinger) {
+    /// use futures::StreamExt;
+    /// use surge_ping::{PingEvent, PingPlan};
+    ///
+    /// let mut events = pinger.events(PingPlan::new().count(10));
+    /// while let Some(event) = events.next().await {
+    ///     match event {
+    ///         PingEvent::Outcome { seq, result, reordered } => {
+    ///             println!("icmp_seq={} {:?} reordered={}", seq, result, reordered)
+    ///         }
+    ///         PingEvent::Duplicate { seq, rtt } => println!("icmp_seq={} {:?} DUP!", seq, rtt),
+    ///         PingEvent::Late { seq, late_by, .. } => {
+    ///             println!("icmp_seq={} {:?} after the timeout", seq, late_by)
+    ///         }
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// Duplicate and late replies are recognized for the last 128 requests, and only
+    /// while the stream is alive. Once the last outcome is in, the stream waits the
+    /// timeout of the pinger for further replies to the last requests before it ends,
+    /// unless it ends by the deadline of `plan`. A pinger has at most one such stream, creating another
+    /// one takes them over.
+    pub fn events(&self, plan: PingPlan) -> PingEvents<'_> {
+        PingEvents::new(self, self.cache.subscribe(), plan, self.timeout)
+    }
+
+    /// Find the routers on the way to the destination by sending probes with increasing
+    /// TTL (or hop limit), until the destination answers, a hop reports it unreachable or
+    /// `plan` runs out of hops.
+    ///
+    /// Routers answer with ICMP errors, which are only delivered to `Type::RAW` sockets, so
+    /// with `Type::DGRAM` the intermediate hops time out.
+    ///
+    /// Probes that fail otherwise, e.g. with a corrupt payload, are recorded as
+    /// `Probe::Failed` and the trace goes on. Only failing to send a probe ends it with an
+    /// error.
+    ///
+    /// ```rust,no_run
+    /// # async fn run(pinger: surge_ping::Pinger) -> Result<(), surge_ping::SurgeError> {
+    /// use surge_ping::TracePlan;
+    ///
+    /// let trace = pinger.traceroute(TracePlan::new().max_hops(16)).await?;
+    /// for hop in &trace.hops {
+    ///     println!("{}", hop);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn traceroute(&self, plan: TracePlan) -> Result<Trace> {
+        traceroute::trace(self, plan).await
     }
-}
 
-async fn clear_mapping_key(
-    key: Uuid,
-    mapping: Arc<TokioMutex<HashMap<Uuid, mpsc::Sender<Message>>>>,
-    mut rx: broadcast::Receiver<()>,
-) {
-    if rx.recv().await.is_ok() {
-        mapping.lock().await.remove(&key);
+    /// Find the largest packet that reaches the destination and comes back unfragmented,
+    /// by searching the payload size between the bounds of `plan`. The payload size is
+    /// restored afterwards.
+    ///
+    /// Sizes are ruled out by routers answering Fragmentation Needed (IPv4) or Packet Too
+    /// Big (IPv6), whose reported MTU is tried next, by the kernel refusing to send them,
+    /// or by no reply before the timeout of the `Pinger`, for paths that drop such errors.
+    /// The socket needs `ConfigBuilder::dont_fragment` for both families: without it IPv4
+    /// requests are fragmented by the kernel and routers, and IPv6 requests by the kernel,
+    /// so every size gets through and the search ends at the maximum of `plan`. The
+    /// errors of routers are only delivered to `Type::RAW` sockets.
+    ///
+    /// ```rust,no_run
+    /// # async fn run(mut pinger: surge_ping::Pinger) -> Result<(), surge_ping::SurgeError> {
+    /// use std::time::Duration;
+    /// use surge_ping::MtuPlan;
+    ///
+    /// pinger.timeout(Duration::from_millis(500));
+    /// let path = pinger.discover_mtu(MtuPlan::new().max(9000)).await?;
+    /// println!("path MTU {} after {} probes", path.mtu, path.probes);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails with the error of the smallest size of `plan` if that does not get through.
+    pub async fn discover_mtu(&mut self, plan: MtuPlan) -> Result<PathMtu> {
+        pmtu::discover(self, plan).await
     }
 }
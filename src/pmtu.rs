@@ -0,0 +1,210 @@
+use std::{convert::TryFrom, io, net::IpAddr};
+
+use crate::{
+    error::{Result, SurgeError},
+    icmp::{IcmpError, Unreachable},
+    Pinger,
+};
+
+/// The search of [`Pinger::discover_mtu`](struct.Pinger.html#method.discover_mtu), in
+/// bytes of IP packet: IP header, ICMP header and payload.
+#[derive(Debug, Clone)]
+pub struct MtuPlan {
+    min: Option<u16>,
+    max: u16,
+    probes: usize,
+    start_sequence: u16,
+}
+
+impl Default for MtuPlan {
+    fn default() -> Self {
+        MtuPlan {
+            min: None,
+            max: 1500,
+            probes: 2,
+            start_sequence: 0,
+        }
+    }
+}
+
+impl MtuPlan {
+    /// Search between the minimum MTU of the address family and 1500.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Smallest MTU tried, which has to get through. (default: 68 for IPv4, 1280 for IPv6)
+    pub fn min(mut self, mtu: u16) -> Self {
+        self.min = Some(mtu);
+        self
+    }
+
+    /// Largest MTU tried. (default: 1500)
+    pub fn max(mut self, mtu: u16) -> Self {
+        self.max = mtu;
+        self
+    }
+
+    /// Number of requests sent of a size that gets no answer before it is taken as too
+    /// large, at least 1. (default: 2)
+    pub fn probes(mut self, probes: usize) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+
+    /// Sequence number of the first request, following ones wrap around at `u16::MAX`.
+    /// (default: 0)
+    pub fn start_sequence(mut self, seq_cnt: u16) -> Self {
+        self.start_sequence = seq_cnt;
+        self
+    }
+}
+
+/// The result of [`Pinger::discover_mtu`](struct.Pinger.html#method.discover_mtu).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathMtu {
+    /// Largest IP packet that got an echo reply.
+    pub mtu: u16,
+    /// The router whose Fragmentation Needed or Packet Too Big error reported `mtu`, if
+    /// one did.
+    pub reported_by: Option<IpAddr>,
+    /// Number of requests sent.
+    pub probes: usize,
+}
+
+/// What became of the requests of one size.
+enum Outcome {
+    Fits,
+    /// Refused by a router, which may report its MTU, or by the local interface.
+    TooBig {
+        reported: Option<(u16, IpAddr)>,
+        error: SurgeError,
+    },
+    /// Nothing came back.
+    Lost(SurgeError),
+}
+
+struct Search<'a> {
+    pinger: &'a mut Pinger,
+    header: u16,
+    probes: usize,
+    seq_cnt: u16,
+    sent: usize,
+}
+
+impl Search<'_> {
+    async fn probe(&mut self, mtu: u16) -> Result<Outcome> {
+        self.pinger
+            .size(usize::from(mtu.saturating_sub(self.header)));
+        let mut last = None;
+        for _ in 0..self.probes {
+            let seq_cnt = self.seq_cnt;
+            self.seq_cnt = self.seq_cnt.wrapping_add(1);
+            self.sent += 1;
+            let e = match self.pinger.ping(seq_cnt).await {
+                Ok(_) => return Ok(Outcome::Fits),
+                Err(e) => e,
+            };
+            let too_big = match &e {
+                SurgeError::IcmpError { from, error, .. } => {
+                    too_big(error).map(|reported| reported.map(|reported| (reported, *from)))
+                }
+                SurgeError::IOError(io) if is_emsgsize(io) => Some(None),
+                SurgeError::Timeout { .. } => {
+                    last = Some(e);
+                    continue;
+                }
+                _ => None,
+            };
+            return match too_big {
+                Some(reported) => Ok(Outcome::TooBig { reported, error: e }),
+                None => Err(e),
+            };
+        }
+        Ok(Outcome::Lost(
+            last.expect("at least one request is sent per size"),
+        ))
+    }
+}
+
+/// `Some` for errors saying the packet is too big, with the MTU of the next hop if the
+/// router reported one.
+fn too_big(error: &IcmpError) -> Option<Option<u16>> {
+    match error {
+        IcmpError::DestinationUnreachable(Unreachable::FragmentationNeeded { next_hop_mtu }) => {
+            Some((*next_hop_mtu != 0).then_some(*next_hop_mtu))
+        }
+        IcmpError::PacketTooBig { mtu } => Some(u16::try_from(*mtu).ok()),
+        _ => None,
+    }
+}
+
+/// Whether the kernel refused to send a request larger than the MTU of the interface.
+fn is_emsgsize(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    let emsgsize = libc::EMSGSIZE;
+    #[cfg(windows)]
+    let emsgsize = 10040; // WSAEMSGSIZE
+    e.raw_os_error() == Some(emsgsize)
+}
+
+pub(crate) async fn discover(pinger: &mut Pinger, plan: MtuPlan) -> Result<PathMtu> {
+    let (header, min) = match pinger.destination {
+        IpAddr::V4(_) => (20 + 8, 68),
+        IpAddr::V6(_) => (40 + 8, 1280),
+    };
+    let size = pinger.size;
+    let mut search = Search {
+        pinger,
+        header,
+        probes: plan.probes,
+        seq_cnt: plan.start_sequence,
+        sent: 0,
+    };
+    let result = binary_search(&mut search, plan.min.unwrap_or(min), plan.max).await;
+    let probes = search.sent;
+    search.pinger.size(size);
+    result.map(|(mtu, reported_by)| PathMtu {
+        mtu,
+        reported_by,
+        probes,
+    })
+}
+
+/// The largest MTU in `min..=max` that gets a reply, and the router that reported it.
+async fn binary_search(
+    search: &mut Search<'_>,
+    min: u16,
+    max: u16,
+) -> Result<(u16, Option<IpAddr>)> {
+    match search.probe(min).await? {
+        Outcome::Fits => {}
+        Outcome::TooBig { error, .. } | Outcome::Lost(error) => return Err(error),
+    }
+    let (mut lo, mut hi) = (min, max.max(min));
+    // The MTU reported last, tried before bisecting further.
+    let mut report: Option<(u16, IpAddr)> = None;
+    let mut next = Some(hi);
+    while lo < hi {
+        let mtu = next.take().unwrap_or(lo + (hi - lo + 1) / 2);
+        match search.probe(mtu).await? {
+            Outcome::Fits => lo = mtu,
+            Outcome::TooBig {
+                reported: Some((reported, from)),
+                ..
+            } if reported < mtu => {
+                hi = mtu - 1;
+                if reported >= lo {
+                    hi = reported;
+                    report = Some((reported, from));
+                    next = (reported > lo).then_some(reported);
+                }
+            }
+            Outcome::TooBig { .. } | Outcome::Lost(_) => hi = mtu - 1,
+        }
+    }
+    let reported_by = report
+        .filter(|(reported, _)| *reported == lo)
+        .map(|(_, from)| from);
+    Ok((lo, reported_by))
+}
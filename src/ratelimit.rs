@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::time::sleep_until;
+
+/// A token bucket: `rate` requests per second on average, with up to `burst` of them
+/// sent back to back after a quiet period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    rate: f64,
+    burst: u32,
+}
+
+/// The lowest rate accepted, one request a day.
+const MIN_RATE: f64 = 1.0 / 86_400.0;
+/// The highest rate accepted, one request a nanosecond.
+const MAX_RATE: f64 = 1e9;
+
+/// The time between two requests at `rate` requests per second, which is clamped to
+/// between one a day and one a nanosecond.
+pub(crate) fn rate_interval(rate: f64) -> Duration {
+    Duration::from_secs_f64(1.0 / rate.clamp(MIN_RATE, MAX_RATE))
+}
+
+impl RateLimit {
+    /// At most `rate` requests per second and `burst` at once. A rate that is not
+    /// positive means 1, others are clamped to between one a day and one a nanosecond,
+    /// the burst is at least 1.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        RateLimit {
+            rate: if rate > 0.0 {
+                rate.clamp(MIN_RATE, MAX_RATE)
+            } else {
+                1.0
+            },
+            burst: burst.max(1),
+        }
+    }
+
+    /// Requests per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Requests sent back to back after a quiet period.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    fn interval(&self) -> Duration {
+        rate_interval(self.rate)
+    }
+}
+
+/// How long requests waited for the rate limits of a [`Client`](struct.Client.html), see
+/// [`Client::rate_limit_stats`](struct.Client.html#method.rate_limit_stats).
+///
+/// The counters only grow, take the difference of two snapshots for a rate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RateLimitStats {
+    /// Requests that went through the limiter.
+    pub requests: u64,
+    /// Requests that had to wait for a token.
+    pub delayed: u64,
+    /// Time all requests spent waiting.
+    pub total_wait: Duration,
+    /// The longest wait of a single request.
+    pub max_wait: Duration,
+}
+
+/// A token bucket kept as the time the next request would be sent at the average rate
+/// (the generic cell rate algorithm). Requests reserve their slot right away and then
+/// sleep until it comes, so they are served in order. A request cancelled while it sleeps
+/// gives its slot back.
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    next: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Bucket { limit, next: now }
+    }
+
+    /// Reserve a token, returning when it may be used.
+    fn reserve(&mut self, now: Instant) -> Instant {
+        let interval = self.limit.interval();
+        let tolerance = interval.saturating_mul(self.limit.burst - 1);
+        let next = self.next.max(now);
+        self.next = next + interval;
+        next.checked_sub(tolerance).unwrap_or(now).max(now)
+    }
+
+    /// Give back a token reserved but not used. Tokens are all alike, so this frees the
+    /// last slot reserved, moving nobody else's.
+    fn refund(&mut self) {
+        let interval = self.limit.interval();
+        self.next = self.next.checked_sub(interval).unwrap_or(self.next);
+    }
+
+    /// Whether the bucket is full again, so forgetting it changes nothing.
+    fn idle(&self, now: Instant) -> bool {
+        self.next <= now
+    }
+}
+
+/// The rate limits of one `Client`, shared by its pingers.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    global: Option<Mutex<Bucket>>,
+    per_destination: Option<(RateLimit, Mutex<Destinations>)>,
+    requests: AtomicU64,
+    delayed: AtomicU64,
+    total_wait: AtomicU64,
+    max_wait: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Destinations {
+    buckets: HashMap<IpAddr, Bucket>,
+    // Idle buckets are dropped once the map doubles, so it does not grow with every
+    // destination ever pinged.
+    prune_at: usize,
+}
+
+/// A token reserved by `RateLimiter::acquire`, refunded if the acquire is cancelled
+/// before the request may be sent.
+struct Reservation<'a> {
+    bucket: Option<Held<'a>>,
+}
+
+enum Held<'a> {
+    Global(&'a Mutex<Bucket>),
+    Destination(&'a Mutex<Destinations>, IpAddr),
+}
+
+impl Reservation<'_> {
+    /// The request is sent, so the token is used.
+    fn keep(mut self) {
+        self.bucket = None;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        match self.bucket.take() {
+            Some(Held::Global(bucket)) => bucket.lock().refund(),
+            // A pruned bucket was full again anyway.
+            Some(Held::Destination(destinations, destination)) => {
+                if let Some(bucket) = destinations.lock().buckets.get_mut(&destination) {
+                    bucket.refund();
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+impl RateLimiter {
+    /// A limiter for the given limits, `None` if there are none.
+    pub(crate) fn new(
+        global: Option<RateLimit>,
+        per_destination: Option<RateLimit>,
+    ) -> Option<RateLimiter> {
+        if global.is_none() && per_destination.is_none() {
+            return None;
+        }
+        let now = Instant::now();
+        Some(RateLimiter {
+            global: global.map(|limit| Mutex::new(Bucket::new(limit, now))),
+            per_destination: per_destination.map(|limit| (limit, Mutex::default())),
+            requests: AtomicU64::new(0),
+            delayed: AtomicU64::new(0),
+            total_wait: AtomicU64::new(0),
+            max_wait: AtomicU64::new(0),
+        })
+    }
+
+    /// Wait until a request to `destination` may be sent.
+    pub(crate) async fn acquire(&self, destination: IpAddr) {
+        let start = Instant::now();
+        let mut delayed = false;
+        // The destination first, so a busy destination does not hold global tokens
+        // while it waits.
+        let mut reservations = Vec::with_capacity(2);
+        if let Some((limit, destinations)) = &self.per_destination {
+            let at = {
+                let now = Instant::now();
+                let mut destinations = destinations.lock();
+                if destinations.buckets.len() >= destinations.prune_at {
+                    destinations.buckets.retain(|_, bucket| !bucket.idle(now));
+                    destinations.prune_at = (destinations.buckets.len() * 2).max(64);
+                }
+                destinations
+                    .buckets
+                    .entry(destination)
+                    .or_insert_with(|| Bucket::new(*limit, now))
+                    .reserve(now)
+            };
+            reservations.push(Reservation {
+                bucket: Some(Held::Destination(destinations, destination)),
+            });
+            delayed |= wait_until(at).await;
+        }
+        if let Some(global) = &self.global {
+            let at = global.lock().reserve(Instant::now());
+            reservations.push(Reservation {
+                bucket: Some(Held::Global(global)),
+            });
+            delayed |= wait_until(at).await;
+        }
+        reservations.into_iter().for_each(Reservation::keep);
+
+        let wait = start.elapsed();
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if delayed {
+            self.delayed.fetch_add(1, Ordering::Relaxed);
+        }
+        let nanos = wait.as_nanos() as u64;
+        self.total_wait.fetch_add(nanos, Ordering::Relaxed);
+        self.max_wait.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            delayed: self.delayed.load(Ordering::Relaxed),
+            total_wait: Duration::from_nanos(self.total_wait.load(Ordering::Relaxed)),
+            max_wait: Duration::from_nanos(self.max_wait.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Sleep until `at` if it is in the future, returning whether it was.
+async fn wait_until(at: Instant) -> bool {
+    if at <= Instant::now() {
+        return false;
+    }
+    sleep_until(at.into()).await;
+    true
+}
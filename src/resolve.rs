@@ -0,0 +1,300 @@
+use std::{
+    fmt,
+    future::Future,
+    io,
+    net::IpAddr,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::net::lookup_host;
+use tracing::warn;
+
+use crate::{
+    error::Result, icmp::IcmpPacket, payload::PayloadPattern, transport::SendOptions, Client,
+    DualStackClient, Pinger,
+};
+
+/// Which of the resolved addresses of a host to ping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FamilyPreference {
+    /// The first address returned by the resolver.
+    #[default]
+    Any,
+    /// Only IPv4 addresses.
+    V4Only,
+    /// Only IPv6 addresses.
+    V6Only,
+    /// An IPv4 address if there is one, otherwise an IPv6 address.
+    PreferV4,
+    /// An IPv6 address if there is one, otherwise an IPv4 address.
+    PreferV6,
+}
+
+impl FamilyPreference {
+    fn pick(self, addrs: &[IpAddr]) -> Option<IpAddr> {
+        let v4 = addrs.iter().copied().find(IpAddr::is_ipv4);
+        let v6 = addrs.iter().copied().find(IpAddr::is_ipv6);
+        match self {
+            FamilyPreference::Any => addrs.first().copied(),
+            FamilyPreference::V4Only => v4,
+            FamilyPreference::V6Only => v6,
+            FamilyPreference::PreferV4 => v4.or(v6),
+            FamilyPreference::PreferV6 => v6.or(v4),
+        }
+    }
+}
+
+/// The future returned by [`Resolver::resolve`](trait.Resolver.html#tymethod.resolve).
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<IpAddr>>> + Send + 'a>>;
+
+/// Turns host names into addresses for a [`HostPinger`](struct.HostPinger.html).
+///
+/// The resolver of the system is used by default, implement this trait to resolve names
+/// some other way, such as the names of a [`MockNetwork`](mock/struct.MockNetwork.html)
+/// in tests.
+pub trait Resolver: Send + Sync + 'static {
+    /// The addresses of `host`, a name or an address literal, in order of preference.
+    fn resolve<'a>(&'a self, host: &'a str) -> ResolveFuture<'a>;
+}
+
+/// The resolver of the system, through `tokio::net::lookup_host`.
+struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            Ok(lookup_host((host, 0))
+                .await?
+                .map(|addr| addr.ip())
+                .collect())
+        })
+    }
+}
+
+/// How [`Client::pinger_for_host`](struct.Client.html#method.pinger_for_host) and
+/// [`DualStackClient::pinger_for_host`](struct.DualStackClient.html#method.pinger_for_host)
+/// turn a host name into an address.
+#[derive(Clone)]
+pub struct ResolvePlan {
+    pub(crate) family: FamilyPreference,
+    refresh: Option<Duration>,
+    resolver: Arc<dyn Resolver>,
+}
+
+impl Default for ResolvePlan {
+    fn default() -> Self {
+        ResolvePlan {
+            family: FamilyPreference::default(),
+            refresh: None,
+            resolver: Arc::new(SystemResolver),
+        }
+    }
+}
+
+impl fmt::Debug for ResolvePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResolvePlan")
+            .field("family", &self.family)
+            .field("refresh", &self.refresh)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResolvePlan {
+    /// Resolve once and use the first address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Which address family to use. (default: `FamilyPreference::Any`)
+    pub fn family(mut self, family: FamilyPreference) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Resolve the host again before pinging once `refresh` has passed since the last
+    /// resolution, so long running pingers follow DNS changes. (default: never)
+    pub fn refresh(mut self, refresh: Duration) -> Self {
+        self.refresh = Some(refresh);
+        self
+    }
+
+    /// Resolve with `resolver` instead of the resolver of the system.
+    pub fn resolver<R: Resolver>(mut self, resolver: R) -> Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+}
+
+/// Where a `HostPinger` gets the pinger of a new address.
+#[derive(Clone)]
+pub(crate) enum Clients {
+    Single(Client),
+    DualStack(DualStackClient),
+}
+
+impl Clients {
+    async fn pinger(&self, addr: IpAddr) -> Pinger {
+        match self {
+            Clients::Single(client) => client.pinger(addr).await,
+            Clients::DualStack(client) => client.pinger(addr).await,
+        }
+    }
+}
+
+/// A [`Pinger`](struct.Pinger.html) for a host name, returned by
+/// [`Client::pinger_for_host`](struct.Client.html#method.pinger_for_host) and
+/// [`DualStackClient::pinger_for_host`](struct.DualStackClient.html#method.pinger_for_host).
+///
+/// Settings such as `timeout`, `size` and `ttl` carry over when the host moves to another
+/// address.
+pub struct HostPinger {
+    clients: Clients,
+    host: String,
+    plan: ResolvePlan,
+    pinger: Pinger,
+    resolved: Instant,
+}
+
+impl HostPinger {
+    pub(crate) async fn new(
+        clients: Clients,
+        host: &str,
+        plan: ResolvePlan,
+    ) -> io::Result<HostPinger> {
+        let addr = resolve(&plan, host).await?;
+        let pinger = clients.pinger(addr).await;
+        Ok(HostPinger {
+            clients,
+            host: host.to_string(),
+            plan,
+            pinger,
+            resolved: Instant::now(),
+        })
+    }
+
+    /// The host name this pinger follows.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The address currently pinged.
+    pub fn address(&self) -> IpAddr {
+        self.pinger.destination
+    }
+
+    /// See [`Pinger::size`](struct.Pinger.html#method.size).
+    pub fn size(&mut self, size: usize) -> &mut HostPinger {
+        self.pinger.size(size);
+        self
+    }
+
+    /// See [`Pinger::pattern`](struct.Pinger.html#method.pattern).
+    pub fn pattern(&mut self, pattern: PayloadPattern) -> &mut HostPinger {
+        self.pinger.pattern(pattern);
+        self
+    }
+
+    /// See [`Pinger::payload_token`](struct.Pinger.html#method.payload_token).
+    pub fn payload_token(&mut self, enable: bool) -> &mut HostPinger {
+        self.pinger.payload_token(enable);
+        self
+    }
+
+    /// See [`Pinger::verify_payload`](struct.Pinger.html#method.verify_payload).
+    pub fn verify_payload(&mut self, verify: bool) -> &mut HostPinger {
+        self.pinger.verify_payload(verify);
+        self
+    }
+
+    /// See [`Pinger::timeout`](struct.Pinger.html#method.timeout).
+    pub fn timeout(&mut self, timeout: Duration) -> &mut HostPinger {
+        self.pinger.timeout(timeout);
+        self
+    }
+
+    /// See [`Pinger::ttl`](struct.Pinger.html#method.ttl).
+    pub fn ttl(&mut self, ttl: u8) -> &mut HostPinger {
+        self.pinger.ttl(ttl);
+        self
+    }
+
+    /// See [`Pinger::tos`](struct.Pinger.html#method.tos).
+    pub fn tos(&mut self, tos: u8) -> &mut HostPinger {
+        self.pinger.tos(tos);
+        self
+    }
+
+    /// The `Pinger` of the current address, which stops following the host. Use it for
+    /// what `HostPinger` has no method for, such as `Pinger::stream`.
+    pub fn into_pinger(self) -> Pinger {
+        self.pinger
+    }
+
+    /// Resolve the host now, returning whether its address changed.
+    pub async fn refresh(&mut self) -> io::Result<bool> {
+        let addr = resolve(&self.plan, &self.host).await?;
+        self.resolved = Instant::now();
+        if addr == self.pinger.destination {
+            return Ok(false);
+        }
+        let mut pinger = self.clients.pinger(addr).await;
+        pinger.inherit(&self.pinger);
+        self.pinger = pinger;
+        Ok(true)
+    }
+
+    /// Resolve the host again if the refresh interval of the `ResolvePlan` has passed,
+    /// keeping the previous address if that fails.
+    async fn refresh_due(&mut self) {
+        if let Some(refresh) = self.plan.refresh {
+            if self.resolved.elapsed() >= refresh {
+                if let Err(e) = self.refresh().await {
+                    warn!("resolve {} failed: {}", self.host, e);
+                    self.resolved = Instant::now();
+                }
+            }
+        }
+    }
+
+    /// Send Ping request with sequence number and wait for the reply, resolving the
+    /// host again first if the refresh interval of the `ResolvePlan` has passed.
+    ///
+    /// Returns the address the request was sent to along with the outcome. If resolving
+    /// fails, the previous address is pinged.
+    pub async fn ping(&mut self, seq_cnt: u16) -> (IpAddr, Result<(IcmpPacket, Duration)>) {
+        self.refresh_due().await;
+        let addr = self.pinger.destination;
+        (addr, self.pinger.ping(seq_cnt).await)
+    }
+
+    /// Like [`ping`](#method.ping), with `options` overriding the settings of the pinger
+    /// for this request only.
+    pub async fn ping_with(
+        &mut self,
+        seq_cnt: u16,
+        options: SendOptions,
+    ) -> (IpAddr, Result<(IcmpPacket, Duration)>) {
+        self.refresh_due().await;
+        let addr = self.pinger.destination;
+        (addr, self.pinger.ping_with(seq_cnt, options).await)
+    }
+}
+
+async fn resolve(plan: &ResolvePlan, host: &str) -> io::Result<IpAddr> {
+    let addrs = plan.resolver.resolve(host).await?;
+    let family = plan.family;
+    family.pick(&addrs).ok_or_else(|| {
+        let kind = match family {
+            FamilyPreference::V4Only => "IPv4",
+            FamilyPreference::V6Only => "IPv6",
+            _ => "IP",
+        };
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no {} address for {}", kind, host),
+        )
+    })
+}
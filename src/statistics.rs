@@ -0,0 +1,287 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Result,
+    icmp::{IcmpError, IcmpPacket},
+    PingEvent, SurgeError,
+};
+
+/// Accumulates `Pinger` results into the numbers printed by `ping` when it exits.
+///
+/// ```rust,no_run
+/// # async fn run(pinger: surge_ping::Pinger) {
+/// use surge_ping::PingStatistics;
+///
+/// let mut stats = PingStatistics::new();
+/// for seq in 0..5 {
+///     stats.record(&pinger.ping(seq).await);
+/// }
+/// println!("{}", stats);
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PingStatistics {
+    transmitted: u64,
+    received: u64,
+    duplicates: u64,
+    late: u64,
+    reordered: u64,
+    errors: u64,
+    icmp_errors: BTreeMap<IcmpError, u64>,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    sum: Duration,
+    sum_sq: f64,
+    started: Option<Instant>,
+    updated: Option<Instant>,
+}
+
+impl PingStatistics {
+    /// Statistics with nothing recorded yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for one request and its outcome, as returned by `Pinger::ping` or yielded
+    /// by `Pinger::stream`.
+    ///
+    /// Only echo replies count as received. Replies failing the payload check
+    /// (`SurgeError::CorruptPayload`) count as errors, so they add to the loss although
+    /// they did arrive.
+    pub fn record(&mut self, result: &Result<(IcmpPacket, Duration)>) {
+        self.transmitted += 1;
+        match result {
+            Ok((_, rtt)) => self.add_rtt(*rtt),
+            Err(SurgeError::IcmpError { error, .. }) => {
+                *self.icmp_errors.entry(*error).or_default() += 1;
+                self.errors += 1;
+            }
+            Err(SurgeError::Timeout { .. }) => {}
+            Err(_) => self.errors += 1,
+        }
+        self.touch();
+    }
+
+    /// Account for a reply received for a request that was already answered.
+    pub fn record_duplicate(&mut self) {
+        self.duplicates += 1;
+        self.touch();
+    }
+
+    /// Account for a reply received after its request timed out.
+    pub fn record_late(&mut self) {
+        self.late += 1;
+        self.touch();
+    }
+
+    /// Account for an event yielded by `Pinger::events`.
+    pub fn record_event(&mut self, event: &PingEvent) {
+        match event {
+            PingEvent::Outcome {
+                result, reordered, ..
+            } => {
+                self.record(result);
+                if *reordered {
+                    self.reordered += 1;
+                }
+            }
+            PingEvent::Duplicate { .. } => self.record_duplicate(),
+            PingEvent::Late { .. } => self.record_late(),
+        }
+    }
+
+    fn add_rtt(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+        self.sum += rtt;
+        self.sum_sq += rtt.as_secs_f64() * rtt.as_secs_f64();
+    }
+
+    fn touch(&mut self) {
+        let now = Instant::now();
+        self.started.get_or_insert(now);
+        self.updated = Some(now);
+    }
+
+    /// Number of requests recorded.
+    pub fn transmitted(&self) -> u64 {
+        self.transmitted
+    }
+
+    /// Number of requests answered by an echo reply.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Number of duplicate replies.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Number of replies that came after their request timed out, they count as lost.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    /// Number of replies that came after the reply to a request sent later.
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    /// Number of requests answered by an ICMP error or failed otherwise, including replies
+    /// with a corrupt payload.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// ICMP errors received, by error.
+    pub fn icmp_errors(&self) -> &BTreeMap<IcmpError, u64> {
+        &self.icmp_errors
+    }
+
+    /// Percentage of requests without an echo reply, see [`record`](#method.record).
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        (self.transmitted - self.received) as f64 * 100.0 / self.transmitted as f64
+    }
+
+    /// Shortest round trip time of the echo replies, `None` before the first one.
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    /// Longest round trip time of the echo replies, `None` before the first one.
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    /// Average round trip time of the echo replies, `None` before the first one.
+    pub fn avg(&self) -> Option<Duration> {
+        if self.received == 0 {
+            return None;
+        }
+        let nanos = self.sum.as_nanos() / u128::from(self.received);
+        Some(Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        ))
+    }
+
+    /// Mean deviation of the round trip times, computed like `ping` does.
+    pub fn mdev(&self) -> Option<Duration> {
+        let avg = self.avg()?.as_secs_f64();
+        let variance = self.sum_sq / self.received as f64 - avg * avg;
+        Some(Duration::from_secs_f64(variance.max(0.0).sqrt()))
+    }
+
+    /// Time between the first and the last recorded result.
+    pub fn elapsed(&self) -> Duration {
+        match (self.started, self.updated) {
+            (Some(started), Some(updated)) => updated - started,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Add the results of `other`, e.g. to aggregate several pingers.
+    pub fn merge(&mut self, other: &PingStatistics) {
+        self.transmitted += other.transmitted;
+        self.received += other.received;
+        self.duplicates += other.duplicates;
+        self.late += other.late;
+        self.reordered += other.reordered;
+        self.errors += other.errors;
+        for (key, count) in &other.icmp_errors {
+            *self.icmp_errors.entry(*key).or_default() += count;
+        }
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        self.started = self.started.into_iter().chain(other.started).min();
+        self.updated = self.updated.into_iter().chain(other.updated).max();
+    }
+
+    /// Forget everything recorded so far, starting a new window.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Return the current window and start a new one.
+    pub fn take(&mut self) -> PingStatistics {
+        std::mem::take(self)
+    }
+}
+
+/// The summary printed by iputils `ping`:
+///
+/// ```text
+/// 5 packets transmitted, 4 received, +1 errors, 20% packet loss, time 4005ms
+/// rtt min/avg/max/mdev = 0.045/0.060/0.081/0.012 ms
+/// ```
+impl fmt::Display for PingStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packets transmitted, {} received",
+            self.transmitted, self.received
+        )?;
+        if self.duplicates > 0 {
+            write!(f, ", +{} duplicates", self.duplicates)?;
+        }
+        if self.errors > 0 {
+            write!(f, ", +{} errors", self.errors)?;
+        }
+        let loss = format!("{:.4}", self.loss());
+        let loss = loss.trim_end_matches('0').trim_end_matches('.');
+        write!(
+            f,
+            ", {}% packet loss, time {}ms",
+            loss,
+            self.elapsed().as_millis()
+        )?;
+        if let (Some(min), Some(avg), Some(max), Some(mdev)) =
+            (self.min(), self.avg(), self.max(), self.mdev())
+        {
+            let ms = |dur: Duration| dur.as_secs_f64() * 1000.0;
+            write!(
+                f,
+                "\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                ms(min),
+                ms(avg),
+                ms(max),
+                ms(mdev)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn avg_of_more_than_u32_max_replies() {
+        let received = u64::from(u32::MAX) + 2;
+        let stats = PingStatistics {
+            received,
+            sum: Duration::from_millis(3) * 2 + Duration::from_millis(3) * u32::MAX,
+            ..PingStatistics::default()
+        };
+        assert_eq!(stats.avg(), Some(Duration::from_millis(3)));
+
+        let stats = PingStatistics {
+            received: 1 << 32,
+            sum: Duration::from_secs(1 << 32),
+            ..PingStatistics::default()
+        };
+        assert_eq!(stats.avg(), Some(Duration::from_secs(1)));
+    }
+}
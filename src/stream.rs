@@ -0,0 +1,248 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, Stream, StreamExt};
+use tokio::{
+    sync::mpsc,
+    time::{self, Interval, MissedTickBehavior, Sleep},
+};
+
+use crate::{error::Result, icmp::IcmpPacket, Pinger};
+
+/// The schedule of a [`PingStream`](struct.PingStream.html), like the `-i`, `-c` and `-w`
+/// options of the `ping` command.
+#[derive(Debug, Clone)]
+pub struct PingPlan {
+    interval: Duration,
+    count: Option<usize>,
+    deadline: Option<Duration>,
+    start_sequence: u16,
+}
+
+impl Default for PingPlan {
+    fn default() -> Self {
+        PingPlan {
+            interval: Duration::from_secs(1),
+            count: None,
+            deadline: None,
+            start_sequence: 0,
+        }
+    }
+}
+
+/// The shortest time between two requests of a schedule.
+pub(crate) const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+impl PingPlan {
+    /// Ping every second until the stream is dropped.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time between two requests, at least 1ms. (default: 1s)
+    ///
+    /// Requests are sent on a fixed schedule no matter how long replies take, so the
+    /// sending does not drift. If sending falls behind, missed slots are skipped instead
+    /// of being sent in a burst.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// Stop after sending `count` requests, the stream ends once all of them are answered
+    /// or timed out. (default: unlimited)
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// End the stream after `deadline`, regardless of how many requests were sent or are
+    /// still outstanding. (default: none)
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sequence number of the first request, following ones wrap around at `u16::MAX`.
+    /// (default: 0)
+    pub fn start_sequence(mut self, seq_cnt: u16) -> Self {
+        self.start_sequence = seq_cnt;
+        self
+    }
+}
+
+/// The sequence number and outcome of a request, and whether it was overtaken.
+type Ordered = (u16, Result<(IcmpPacket, Duration)>, bool);
+
+type Outcome<'a> = Pin<Box<dyn Future<Output = Ordered> + Send + 'a>>;
+
+/// The stream returned by [`Pinger::stream`](struct.Pinger.html#method.stream), yielding
+/// the sequence number and outcome of each request in the order they complete.
+pub struct PingStream<'a> {
+    pinger: &'a Pinger,
+    plan: PingPlan,
+    interval: Interval,
+    deadline: Option<Pin<Box<Sleep>>>,
+    sent: usize,
+    seq_cnt: u16,
+    in_flight: FuturesUnordered<Outcome<'a>>,
+}
+
+impl<'a> PingStream<'a> {
+    pub(crate) fn new(pinger: &'a Pinger, plan: PingPlan) -> Self {
+        let mut interval = time::interval(plan.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        PingStream {
+            pinger,
+            interval,
+            deadline: plan
+                .deadline
+                .map(|deadline| Box::pin(time::sleep(deadline))),
+            sent: 0,
+            seq_cnt: plan.start_sequence,
+            in_flight: FuturesUnordered::new(),
+            plan,
+        }
+    }
+
+    fn done_sending(&self) -> bool {
+        self.plan.count.is_some_and(|count| self.sent >= count)
+    }
+
+    fn poll_ordered(&mut self, cx: &mut Context<'_>) -> Poll<Option<Ordered>> {
+        let this = self;
+        if let Some(deadline) = this.deadline.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                this.in_flight.clear();
+                return Poll::Ready(None);
+            }
+        }
+
+        while !this.done_sending() && this.interval.poll_tick(cx).is_ready() {
+            let pinger = this.pinger;
+            let seq_cnt = this.seq_cnt;
+            this.in_flight.push(Box::pin(async move {
+                let (outcome, reordered) = pinger.ping_ordered(seq_cnt).await;
+                (seq_cnt, outcome, reordered)
+            }));
+            this.sent += 1;
+            this.seq_cnt = this.seq_cnt.wrapping_add(1);
+        }
+
+        match this.in_flight.poll_next_unpin(cx) {
+            Poll::Ready(Some(outcome)) => Poll::Ready(Some(outcome)),
+            _ if this.done_sending() && this.in_flight.is_empty() => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for PingStream<'_> {
+    type Item = (u16, Result<(IcmpPacket, Duration)>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_ordered(cx)
+            .map(|item| item.map(|(seq, outcome, _)| (seq, outcome)))
+    }
+}
+
+/// An item of [`PingEvents`](struct.PingEvents.html).
+#[derive(Debug)]
+pub enum PingEvent {
+    /// The outcome of a request, like the items of `PingStream`. `reordered` is true if a
+    /// request sent after it was answered first.
+    Outcome {
+        seq: u16,
+        result: Result<(IcmpPacket, Duration)>,
+        reordered: bool,
+    },
+    /// Another reply to a request that was already answered.
+    Duplicate { seq: u16, rtt: Duration },
+    /// A reply to a request that had timed out, `late_by` after the timeout.
+    Late {
+        seq: u16,
+        rtt: Duration,
+        late_by: Duration,
+    },
+}
+
+impl PingEvent {
+    /// The sequence number of the request the event is about.
+    pub fn sequence(&self) -> u16 {
+        match self {
+            PingEvent::Outcome { seq, .. }
+            | PingEvent::Duplicate { seq, .. }
+            | PingEvent::Late { seq, .. } => *seq,
+        }
+    }
+}
+
+/// The stream returned by [`Pinger::events`](struct.Pinger.html#method.events), yielding
+/// the outcome of each request along with the duplicate and late replies, in the order
+/// they happen.
+pub struct PingEvents<'a> {
+    stream: PingStream<'a>,
+    reported: mpsc::Receiver<PingEvent>,
+    /// How long duplicate and late replies are waited for after the last outcome.
+    linger: Duration,
+    /// Set once the outcomes ended, the end of the wait for further replies.
+    lingering: Option<Pin<Box<Sleep>>>,
+}
+
+impl<'a> PingEvents<'a> {
+    pub(crate) fn new(
+        pinger: &'a Pinger,
+        reported: mpsc::Receiver<PingEvent>,
+        plan: PingPlan,
+        linger: Duration,
+    ) -> Self {
+        PingEvents {
+            stream: PingStream::new(pinger, plan),
+            reported,
+            linger,
+            lingering: None,
+        }
+    }
+}
+
+impl Stream for PingEvents<'_> {
+    type Item = PingEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Outcomes first, so a reply comes before its duplicates.
+        if self.lingering.is_none() {
+            match self.stream.poll_ordered(cx) {
+                Poll::Ready(Some((seq, result, reordered))) => {
+                    return Poll::Ready(Some(PingEvent::Outcome {
+                        seq,
+                        result,
+                        reordered,
+                    }))
+                }
+                Poll::Ready(None) => {
+                    // Replies to the last requests can still come, unless the deadline
+                    // passed, then only the ones already reported are taken.
+                    let linger = match &self.stream.deadline {
+                        Some(deadline) if deadline.is_elapsed() => Duration::ZERO,
+                        _ => self.linger,
+                    };
+                    self.lingering = Some(Box::pin(time::sleep(linger)));
+                }
+                Poll::Pending => {}
+            }
+        }
+        match self.reported.poll_recv(cx) {
+            Poll::Ready(Some(event)) => return Poll::Ready(Some(event)),
+            Poll::Ready(None) if self.lingering.is_some() => return Poll::Ready(None),
+            _ => {}
+        }
+        match self.lingering.as_mut() {
+            Some(lingering) => lingering.as_mut().poll(cx).map(|()| None),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    fmt,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    time::Duration,
+};
+
+use futures::{stream, StreamExt};
+use thiserror::Error;
+use tokio::{
+    sync::Mutex,
+    time::{self, Interval, MissedTickBehavior},
+};
+
+use crate::{error::SurgeError, icmp::IcmpError, ratelimit::rate_interval, Pinger};
+
+/// A range of addresses to sweep, parsed from a CIDR block (`10.0.0.0/22`), a range
+/// (`10.0.0.1-10.0.0.50`) or a single address.
+///
+/// Like `fping -g`, the network and broadcast addresses of IPv4 blocks larger than `/31`
+/// are left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    start: IpAddr,
+    end: IpAddr,
+}
+
+/// Why a string is not an [`IpRange`](struct.IpRange.html).
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid address range {0:?}")]
+pub struct RangeParseError(String);
+
+impl IpRange {
+    /// The addresses from `start` to `end`, both included. They must be of the same family
+    /// and in order.
+    pub fn new(start: IpAddr, end: IpAddr) -> Result<IpRange, RangeParseError> {
+        if start.is_ipv4() != end.is_ipv4() || to_bits(start) > to_bits(end) {
+            return Err(RangeParseError(format!("{}-{}", start, end)));
+        }
+        Ok(IpRange { start, end })
+    }
+
+    /// The hosts of the block `addr/prefix`.
+    pub fn cidr(addr: IpAddr, prefix: u8) -> Result<IpRange, RangeParseError> {
+        let bits = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > bits {
+            return Err(RangeParseError(format!("{}/{}", addr, prefix)));
+        }
+        let host_bits = u32::from(bits - prefix);
+        let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0);
+        let mut start = to_bits(addr) & mask;
+        let mut end = start | !mask & (u128::MAX >> (128 - u32::from(bits)));
+        if addr.is_ipv4() && host_bits > 1 {
+            start += 1;
+            end -= 1;
+        }
+        Ok(IpRange {
+            start: from_bits(addr, start),
+            end: from_bits(addr, end),
+        })
+    }
+
+    /// The first address of the range.
+    pub fn start(&self) -> IpAddr {
+        self.start
+    }
+
+    /// The last address of the range.
+    pub fn end(&self) -> IpAddr {
+        self.end
+    }
+
+    /// Number of addresses in the range.
+    pub fn len(&self) -> u128 {
+        (to_bits(self.end) - to_bits(self.start)).saturating_add(1)
+    }
+
+    /// Always false, a range holds at least one address.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Whether `addr` is in the range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        addr.is_ipv4() == self.start.is_ipv4()
+            && (to_bits(self.start)..=to_bits(self.end)).contains(&to_bits(addr))
+    }
+
+    /// The addresses of the range in order.
+    pub fn iter(&self) -> impl Iterator<Item = IpAddr> + Send + 'static {
+        let start = self.start;
+        (to_bits(self.start)..=to_bits(self.end)).map(move |bits| from_bits(start, bits))
+    }
+}
+
+fn to_bits(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr).into(),
+        IpAddr::V6(addr) => addr.into(),
+    }
+}
+
+/// The address of the family of `family` with the value `bits`.
+fn from_bits(family: IpAddr, bits: u128) -> IpAddr {
+    match family {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(bits as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(bits)),
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = RangeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RangeParseError(s.to_string());
+        if let Some((addr, prefix)) = s.split_once('/') {
+            let addr = addr.trim().parse().map_err(|_| invalid())?;
+            let prefix = prefix.trim().parse().map_err(|_| invalid())?;
+            return IpRange::cidr(addr, prefix).map_err(|_| invalid());
+        }
+        if let Some((start, end)) = s.split_once('-') {
+            let start = start.trim().parse().map_err(|_| invalid())?;
+            let end = end.trim().parse().map_err(|_| invalid())?;
+            return IpRange::new(start, end).map_err(|_| invalid());
+        }
+        let addr = s.trim().parse().map_err(|_| invalid())?;
+        Ok(IpRange {
+            start: addr,
+            end: addr,
+        })
+    }
+}
+
+impl From<IpAddr> for IpRange {
+    fn from(addr: IpAddr) -> Self {
+        IpRange {
+            start: addr,
+            end: addr,
+        }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// How [`Client::sweep`](struct.Client.html#method.sweep) probes the hosts, like the
+/// `-i`, `-r` and `-t` options of `fping`.
+#[derive(Debug, Clone)]
+pub struct SweepPlan {
+    concurrency: usize,
+    rate: Option<f64>,
+    retries: usize,
+    timeout: Duration,
+    size: usize,
+    max_hosts: usize,
+}
+
+impl Default for SweepPlan {
+    fn default() -> Self {
+        SweepPlan {
+            concurrency: 64,
+            rate: None,
+            retries: 1,
+            timeout: Duration::from_millis(500),
+            size: 56,
+            max_hosts: 131_072,
+        }
+    }
+}
+
+impl SweepPlan {
+    /// Probe 64 hosts at a time, trying each one twice.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of hosts probed at the same time, at least 1. (default: 64)
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Send at most `rate` requests per second over the whole sweep, retries included,
+    /// clamped to between one a day and one a nanosecond. (default: unlimited)
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = (rate > 0.0).then_some(rate);
+        self
+    }
+
+    /// Requests sent again to a host that did not answer before it is declared dead.
+    /// (default: 1)
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait for the answer to each request. (default: 500ms)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Payload size of the requests. (default: 56)
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Probe only the first `max_hosts` addresses of the ranges, the others are counted
+    /// in `SweepReport::skipped`. The report holds an entry per address probed, so this
+    /// bounds its memory for large IPv6 blocks. (default: 131072, like `fping -g`)
+    pub fn max_hosts(mut self, max_hosts: usize) -> Self {
+        self.max_hosts = max_hosts;
+        self
+    }
+}
+
+/// What a sweep found out about one host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostStatus {
+    /// The host answered, `attempts` is the number of requests it took.
+    Alive { rtt: Duration, attempts: usize },
+    /// A router (or the host itself) answered with an ICMP error such as Destination
+    /// Unreachable, or Time Exceeded in a routing loop.
+    Unreachable { from: IpAddr, error: IcmpError },
+    /// No answer to any request.
+    Dead,
+    /// The requests could not be sent, e.g. because there is no local route.
+    Failed(String),
+}
+
+/// One host of a [`SweepReport`](struct.SweepReport.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostReport {
+    pub addr: IpAddr,
+    pub status: HostStatus,
+}
+
+/// The result of a sweep, one entry per address in the order of the ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SweepReport {
+    pub hosts: Vec<HostReport>,
+    /// Addresses left out because of `SweepPlan::max_hosts`.
+    pub skipped: u128,
+}
+
+impl SweepReport {
+    /// The addresses that answered.
+    pub fn alive(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.with_status(|status| matches!(status, HostStatus::Alive { .. }))
+    }
+
+    /// The addresses that did not answer at all.
+    pub fn dead(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.with_status(|status| *status == HostStatus::Dead)
+    }
+
+    /// The addresses an ICMP error came back for, with the sender of the error.
+    pub fn unreachable(&self) -> impl Iterator<Item = (IpAddr, IpAddr, IcmpError)> + '_ {
+        self.hosts.iter().filter_map(|host| match host.status {
+            HostStatus::Unreachable { from, error } => Some((host.addr, from, error)),
+            _ => None,
+        })
+    }
+
+    fn with_status(
+        &self,
+        pred: impl Fn(&HostStatus) -> bool + 'static,
+    ) -> impl Iterator<Item = IpAddr> + '_ {
+        self.hosts
+            .iter()
+            .filter(move |host| pred(&host.status))
+            .map(|host| host.addr)
+    }
+}
+
+/// Probe every address of `ranges` with a pinger made by `pinger`.
+pub(crate) async fn sweep<F, Fut>(pinger: F, ranges: &[IpRange], plan: SweepPlan) -> SweepReport
+where
+    F: Fn(IpAddr) -> Fut,
+    Fut: Future<Output = Pinger>,
+{
+    let pacer = plan.rate.map(|rate| {
+        let mut interval = time::interval(rate_interval(rate));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Mutex::new(interval)
+    });
+    let total = ranges
+        .iter()
+        .map(IpRange::len)
+        .fold(0, u128::saturating_add);
+    let addrs = ranges
+        .iter()
+        .flat_map(IpRange::iter)
+        .take(plan.max_hosts)
+        .enumerate();
+    let mut hosts: Vec<(usize, HostReport)> = stream::iter(addrs)
+        .map(|(i, addr)| {
+            let pinger = pinger(addr);
+            let plan = &plan;
+            let pacer = pacer.as_ref();
+            async move {
+                let mut pinger = pinger.await;
+                pinger.timeout(plan.timeout).size(plan.size);
+                let status = probe(&pinger, plan.retries, pacer).await;
+                (i, HostReport { addr, status })
+            }
+        })
+        .buffer_unordered(plan.concurrency)
+        .collect()
+        .await;
+    hosts.sort_unstable_by_key(|(i, _)| *i);
+    SweepReport {
+        skipped: total.saturating_sub(hosts.len() as u128),
+        hosts: hosts.into_iter().map(|(_, host)| host).collect(),
+    }
+}
+
+async fn probe(pinger: &Pinger, retries: usize, pacer: Option<&Mutex<Interval>>) -> HostStatus {
+    let mut status = HostStatus::Dead;
+    for attempt in 0..=retries {
+        if let Some(pacer) = pacer {
+            pacer.lock().await.tick().await;
+        }
+        match pinger.ping(attempt as u16).await {
+            Ok((_, rtt)) => {
+                return HostStatus::Alive {
+                    rtt,
+                    attempts: attempt + 1,
+                }
+            }
+            Err(SurgeError::Timeout { .. }) => {}
+            Err(SurgeError::IcmpError { from, error, .. }) => {
+                return HostStatus::Unreachable { from, error }
+            }
+            Err(e) => status = HostStatus::Failed(e.to_string()),
+        }
+    }
+    status
+}
@@ -0,0 +1,535 @@
+//! Socket options and `recvmsg` control messages that neither `socket2` nor tokio expose.
+use std::{
+    io,
+    marker::PhantomData,
+    mem,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    os::unix::io::RawFd,
+    ptr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use socket2::SockAddr;
+
+use crate::{
+    error::SocketOptionError,
+    transport::{RecvMeta, SendOptions},
+};
+
+fn setsockopt(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `setsockopt` an integer option of `libc`, naming it in the error.
+macro_rules! set_option {
+    ($fd:expr, $level:ident, $name:ident, $value:expr) => {
+        setsockopt($fd, libc::$level, libc::$name, $value)
+            .map_err(SocketOptionError::wrap(stringify!($name)))
+    };
+}
+
+/// Ask for the hop limit, the traffic class and the local address of received IPv6
+/// messages.
+pub(crate) fn set_recv_pktinfo_v6(fd: RawFd) -> io::Result<()> {
+    set_option!(fd, IPPROTO_IPV6, IPV6_RECVHOPLIMIT, 1)?;
+    set_option!(fd, IPPROTO_IPV6, IPV6_RECVTCLASS, 1)?;
+    set_option!(fd, IPPROTO_IPV6, IPV6_RECVPKTINFO, 1)
+}
+
+// From <linux/icmpv6.h>, `libc` does not have them.
+const ICMPV6_FILTER: libc::c_int = 1;
+/// ICMPv6 messages other than these, Neighbor Discovery and Multicast Listener Discovery
+/// among others, are of no use to pingers: the errors of RFC 4443 and echo replies.
+const ICMPV6_PASS: [u8; 5] = [1, 2, 3, 4, 129];
+
+/// Only let echo replies and errors through to a `Type::RAW` IPv6 socket, which would
+/// otherwise receive every ICMPv6 message of the host.
+pub(crate) fn set_icmpv6_filter(fd: RawFd) -> io::Result<()> {
+    // a set bit blocks the type
+    let mut filter = [u32::MAX; 8];
+    for kind in ICMPV6_PASS {
+        filter[usize::from(kind >> 5)] &= !(1 << (kind & 31));
+    }
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_ICMPV6,
+            ICMPV6_FILTER,
+            filter.as_ptr() as *const libc::c_void,
+            mem::size_of_val(&filter) as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(SocketOptionError::wrap("ICMP6_FILTER")(
+            io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+/// Ask for the TOS of received IPv4 messages, which `Type::DGRAM` sockets do not see in
+/// front of the ICMP message.
+pub(crate) fn set_recv_tos_v4(fd: RawFd) -> io::Result<()> {
+    set_option!(fd, IPPROTO_IP, IP_RECVTOS, 1)
+}
+
+/// Set the traffic class of sent IPv6 messages, which `socket2` has no setter for.
+pub(crate) fn set_traffic_class(fd: RawFd, tclass: u8) -> io::Result<()> {
+    set_option!(fd, IPPROTO_IPV6, IPV6_TCLASS, tclass.into())
+}
+
+/// Set the Don't Fragment bit of IPv4 requests or forbid fragmenting IPv6 requests.
+/// `PMTUDISC_PROBE` does so without limiting requests to the path MTU the kernel learned.
+pub(crate) fn set_dont_fragment(fd: RawFd, v6: bool) -> io::Result<()> {
+    if v6 {
+        set_option!(
+            fd,
+            IPPROTO_IPV6,
+            IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_PROBE
+        )?;
+        set_option!(fd, IPPROTO_IPV6, IPV6_DONTFRAG, 1)
+    } else {
+        set_option!(fd, IPPROTO_IP, IP_MTU_DISCOVER, libc::IP_PMTUDISC_PROBE)
+    }
+}
+
+/// Allow binding to addresses that are not local and sending from them, for transparent
+/// proxies. Needs `CAP_NET_ADMIN` or `CAP_NET_RAW`.
+pub(crate) fn set_transparent_v4(fd: RawFd) -> io::Result<()> {
+    set_option!(fd, IPPROTO_IP, IP_TRANSPARENT, 1)
+}
+
+/// Like [`set_transparent_v4`], for IPv6 sockets.
+pub(crate) fn set_transparent_v6(fd: RawFd) -> io::Result<()> {
+    set_option!(fd, IPPROTO_IPV6, IPV6_TRANSPARENT, 1)
+}
+
+/// Ask for kernel timestamps of received messages (`SO_TIMESTAMPNS`) and of sent ones,
+/// which are looped back on the error queue (`SO_TIMESTAMPING`).
+pub(crate) fn set_timestamps(fd: RawFd) -> io::Result<()> {
+    set_option!(fd, SOL_SOCKET, SO_TIMESTAMPNS, 1)?;
+    let flags = libc::SOF_TIMESTAMPING_TX_SOFTWARE | libc::SOF_TIMESTAMPING_SOFTWARE;
+    set_option!(fd, SOL_SOCKET, SO_TIMESTAMPING, flags as libc::c_int)
+}
+
+/// An outgoing message with the per-message options as control messages, so concurrent
+/// senders on the same socket do not affect each other.
+pub(crate) struct Outgoing<'a> {
+    target: SockAddr,
+    // u64 keeps the buffer aligned for `cmsghdr`
+    control: [u64; 16],
+    controllen: usize,
+    iov: libc::iovec,
+    _buf: PhantomData<&'a [u8]>,
+}
+
+// The `iovec` only points into `buf`.
+unsafe impl Send for Outgoing<'_> {}
+
+impl<'a> Outgoing<'a> {
+    pub(crate) fn new(buf: &'a [u8], target: &SocketAddr, options: &SendOptions) -> Self {
+        let mut cmsgs: Vec<(libc::c_int, libc::c_int, libc::c_int)> = Vec::new();
+        if let Some(hop_limit) = options.hop_limit {
+            cmsgs.push(match target {
+                SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_TTL, hop_limit.into()),
+                SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT, hop_limit.into()),
+            });
+        }
+        if let Some(tos) = options.tos {
+            cmsgs.push(match target {
+                SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_TOS, tos.into()),
+                SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos.into()),
+            });
+        }
+
+        let mut control = [0u64; 16];
+        let space = unsafe { libc::CMSG_SPACE(mem::size_of::<libc::c_int>() as u32) } as usize;
+        let controllen = space * cmsgs.len();
+        assert!(
+            controllen <= mem::size_of_val(&control),
+            "too many control messages"
+        );
+        // A header only for walking the control buffer, the messages hold no pointers
+        // and stay valid when the buffer moves.
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = controllen as _;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        for (level, kind, value) in cmsgs {
+            unsafe {
+                (*cmsg).cmsg_level = level;
+                (*cmsg).cmsg_type = kind;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::c_int>() as u32) as _;
+                ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, value);
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Outgoing {
+            target: SockAddr::from(*target),
+            control,
+            controllen,
+            iov: libc::iovec {
+                iov_base: buf.as_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            },
+            _buf: PhantomData,
+        }
+    }
+
+    /// The message as a header for `sendmsg`, pointing into `self`.
+    fn msghdr(&mut self) -> libc::msghdr {
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = self.target.as_ptr() as *mut libc::c_void;
+        msg.msg_namelen = self.target.len();
+        msg.msg_iov = &mut self.iov;
+        msg.msg_iovlen = 1;
+        if self.controllen > 0 {
+            msg.msg_control = self.control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = self.controllen as _;
+        }
+        msg
+    }
+}
+
+/// `sendmsg` `buf` to `target` with the per-message `options`.
+pub(crate) fn send_msg(
+    fd: RawFd,
+    buf: &[u8],
+    target: &SocketAddr,
+    options: &SendOptions,
+) -> io::Result<usize> {
+    let mut outgoing = Outgoing::new(buf, target, options);
+    let msg = outgoing.msghdr();
+    let len = unsafe { libc::sendmsg(fd, &msg, 0) };
+    if len == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(len as usize)
+}
+
+/// `sendmmsg` as many of `messages` as the socket takes, returning the number of bytes
+/// sent of each message that went out, in order.
+pub(crate) fn send_mmsg(fd: RawFd, messages: &mut [Outgoing<'_>]) -> io::Result<Vec<usize>> {
+    let mut msgs: Vec<libc::mmsghdr> = messages
+        .iter_mut()
+        .map(|outgoing| libc::mmsghdr {
+            msg_hdr: outgoing.msghdr(),
+            msg_len: 0,
+        })
+        .collect();
+    let sent = unsafe { libc::sendmmsg(fd, msgs.as_mut_ptr(), msgs.len() as _, 0) };
+    if sent == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(msgs[..sent as usize]
+        .iter()
+        .map(|msg| msg.msg_len as usize)
+        .collect())
+}
+
+/// `recvmsg` one message into `buf`, calling `on_cmsg` with the level, type and data of
+/// each control message.
+fn recvmsg(
+    fd: RawFd,
+    buf: &mut [u8],
+    flags: libc::c_int,
+    on_cmsg: impl FnMut(libc::c_int, libc::c_int, *const u8),
+) -> io::Result<(usize, SockAddr)> {
+    // u64 keeps the buffer aligned for `cmsghdr`
+    let mut control = [0u64; 32];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    let (len, addr) = unsafe {
+        SockAddr::init(|storage, addr_len| {
+            msg.msg_name = storage as *mut libc::c_void;
+            msg.msg_namelen = *addr_len;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = mem::size_of_val(&control) as _;
+            let len = libc::recvmsg(fd, &mut msg, flags);
+            if len == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            *addr_len = msg.msg_namelen;
+            Ok(len as usize)
+        })?
+    };
+
+    for_each_cmsg(&msg, on_cmsg);
+    Ok((len, addr))
+}
+
+/// Call `on_cmsg` with the level, type and data of each control message of `msg`.
+fn for_each_cmsg(msg: &libc::msghdr, mut on_cmsg: impl FnMut(libc::c_int, libc::c_int, *const u8)) {
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg) };
+    while !cmsg.is_null() {
+        unsafe {
+            on_cmsg((*cmsg).cmsg_level, (*cmsg).cmsg_type, libc::CMSG_DATA(cmsg));
+            cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+        }
+    }
+}
+
+/// Convert a `CLOCK_REALTIME` kernel timestamp to an `Instant`, assuming it is recent.
+fn to_instant(data: *const u8) -> Instant {
+    let ts = unsafe { ptr::read_unaligned(data as *const libc::timespec) };
+    let ts = UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+    let now = Instant::now();
+    let age = SystemTime::now().duration_since(ts).unwrap_or_default();
+    now.checked_sub(age).unwrap_or(now)
+}
+
+/// `recvmsg` one message into `buf`, collecting the control messages enabled on `fd`.
+pub(crate) fn recv_msg(fd: RawFd, buf: &mut [u8]) -> io::Result<RecvMeta> {
+    let mut ancillary = Ancillary::default();
+    let (len, addr) = recvmsg(fd, buf, 0, |level, kind, data| {
+        ancillary.parse(level, kind, data)
+    })?;
+    ancillary.into_meta(len, addr)
+}
+
+/// `recvmmsg` up to one message into each of the `slot` sized chunks of `buf`, returning
+/// what was received in order.
+pub(crate) fn recv_mmsg(fd: RawFd, buf: &mut [u8], slot: usize) -> io::Result<Vec<RecvMeta>> {
+    let count = buf.len() / slot;
+    let mut controls = vec![[0u64; 32]; count];
+    let mut storages: Vec<libc::sockaddr_storage> = vec![unsafe { mem::zeroed() }; count];
+    let mut iovs: Vec<libc::iovec> = buf
+        .chunks_exact_mut(slot)
+        .map(|chunk| libc::iovec {
+            iov_base: chunk.as_mut_ptr() as *mut libc::c_void,
+            iov_len: chunk.len(),
+        })
+        .collect();
+    let mut msgs: Vec<libc::mmsghdr> = (0..count)
+        .map(|i| {
+            let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+            msg.msg_name = &mut storages[i] as *mut libc::sockaddr_storage as *mut libc::c_void;
+            msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            msg.msg_iov = &mut iovs[i];
+            msg.msg_iovlen = 1;
+            msg.msg_control = controls[i].as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = mem::size_of_val(&controls[i]) as _;
+            libc::mmsghdr {
+                msg_hdr: msg,
+                msg_len: 0,
+            }
+        })
+        .collect();
+
+    let received = unsafe { libc::recvmmsg(fd, msgs.as_mut_ptr(), count as _, 0, ptr::null_mut()) };
+    if received == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    msgs[..received as usize]
+        .iter()
+        .zip(&storages)
+        .map(|(msg, storage)| {
+            let addr = unsafe { SockAddr::new(*storage, msg.msg_hdr.msg_namelen) };
+            let mut ancillary = Ancillary::default();
+            for_each_cmsg(&msg.msg_hdr, |level, kind, data| {
+                ancillary.parse(level, kind, data)
+            });
+            ancillary.into_meta(msg.msg_len as usize, addr)
+        })
+        .collect()
+}
+
+/// What the control messages enabled on the socket say about a received message.
+#[derive(Default)]
+struct Ancillary {
+    hop_limit: Option<u8>,
+    tos: Option<u8>,
+    pktinfo: Option<libc::in6_pktinfo>,
+    timestamp: Option<Instant>,
+}
+
+impl Ancillary {
+    fn parse(&mut self, level: libc::c_int, kind: libc::c_int, data: *const u8) {
+        match (level, kind) {
+            (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                self.hop_limit =
+                    Some(unsafe { ptr::read_unaligned(data as *const libc::c_int) } as u8);
+            }
+            (libc::IPPROTO_IP, libc::IP_TOS) => self.tos = Some(unsafe { *data }),
+            (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => {
+                self.tos = Some(unsafe { ptr::read_unaligned(data as *const libc::c_int) } as u8);
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                self.pktinfo =
+                    Some(unsafe { ptr::read_unaligned(data as *const libc::in6_pktinfo) });
+            }
+            (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) => self.timestamp = Some(to_instant(data)),
+            _ => {}
+        }
+    }
+
+    fn into_meta(self, len: usize, addr: SockAddr) -> io::Result<RecvMeta> {
+        let addr = addr
+            .as_socket()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an IP sender"))?;
+
+        let mut meta = RecvMeta::new(len, addr);
+        meta.hop_limit = self.hop_limit;
+        meta.tos = self.tos;
+        if let Some(info) = self.pktinfo {
+            meta.local_addr = Some(IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+            // `c_int` on Android
+            #[allow(clippy::unnecessary_cast)]
+            let interface = info.ipi6_ifindex as u32;
+            meta.interface = Some(interface);
+        }
+        meta.timestamp = self.timestamp;
+        Ok(meta)
+    }
+}
+
+/// Drain the error queue of `fd` looking for the transmit timestamp of `sent`, the ICMP
+/// message that was just sent.
+pub(crate) fn tx_timestamp(fd: RawFd, sent: &[u8]) -> Option<Instant> {
+    tx_timestamps(fd, &[sent])[0]
+}
+
+/// Drain the error queue of `fd` looking for the transmit timestamps of the ICMP messages
+/// that were just sent, in the order of `sent`.
+///
+/// The kernel loops the whole packet back with its timestamp, the ICMP message is at its
+/// end. The checksum and identifier may have been filled in by the kernel, so only the
+/// type, the sequence number and the payload are compared.
+pub(crate) fn tx_timestamps(fd: RawFd, sent: &[&[u8]]) -> Vec<Option<Instant>> {
+    let mut found = vec![None; sent.len()];
+    let longest = sent.iter().map(|message| message.len()).max().unwrap_or(0);
+    let mut buf = vec![0; longest + 128];
+    loop {
+        let mut timestamp = None;
+        let len = match recvmsg(
+            fd,
+            &mut buf,
+            libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+            |level, kind, data| {
+                if (level, kind) == (libc::SOL_SOCKET, libc::SCM_TIMESTAMPING) {
+                    // the software timestamp comes first, before two hardware ones
+                    timestamp = Some(to_instant(data));
+                }
+            },
+        ) {
+            Ok((len, _)) => len.min(buf.len()),
+            Err(_) => return found,
+        };
+        let looped = &buf[..len];
+        if timestamp.is_some() {
+            if let Some(i) = sent.iter().position(|sent| is_looped(looped, sent)) {
+                found[i] = timestamp;
+            }
+        }
+    }
+}
+
+/// Whether the packet `looped` back on the error queue ends with the ICMP message `sent`.
+fn is_looped(looped: &[u8], sent: &[u8]) -> bool {
+    looped.len() >= sent.len() && sent.len() >= 8 && {
+        let message = &looped[looped.len() - sent.len()..];
+        message[0] == sent[0] && message[6..] == sent[6..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, os::unix::io::AsRawFd};
+
+    use super::*;
+
+    fn timespec(time: SystemTime) -> libc::timespec {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+        libc::timespec {
+            tv_sec: since_epoch.as_secs() as _,
+            tv_nsec: since_epoch.subsec_nanos() as _,
+        }
+    }
+
+    #[test]
+    fn recent_timestamps_become_instants() {
+        let ts = timespec(SystemTime::now() - Duration::from_millis(50));
+        let before = Instant::now();
+        let instant = to_instant(&ts as *const libc::timespec as *const u8);
+        let age = before.saturating_duration_since(instant);
+        assert!(age >= Duration::from_millis(45), "{:?}", age);
+        assert!(age < Duration::from_millis(100), "{:?}", age);
+
+        // clocks going backwards give the current time rather than a later one
+        let ts = timespec(SystemTime::now() + Duration::from_secs(10));
+        let instant = to_instant(&ts as *const libc::timespec as *const u8);
+        assert!(instant <= Instant::now());
+    }
+
+    #[test]
+    fn looped_packets_match_type_sequence_and_payload() {
+        let sent = [8, 0, 0, 0, 0, 0, 0, 7, 1, 2, 3];
+        // ip header in front, checksum and identifier filled in by the kernel
+        let mut looped = vec![0x45; 20];
+        looped.extend_from_slice(&[8, 0, 0xab, 0xcd, 0x12, 0x34, 0, 7, 1, 2, 3]);
+        assert!(is_looped(&looped, &sent));
+        assert!(is_looped(&looped[20..], &sent));
+
+        let mut other_sequence = looped.clone();
+        other_sequence[27] = 8;
+        assert!(!is_looped(&other_sequence, &sent));
+        let mut other_payload = looped.clone();
+        other_payload[30] = 4;
+        assert!(!is_looped(&other_payload, &sent));
+        let mut other_type = looped.clone();
+        other_type[20] = 0;
+        assert!(!is_looped(&other_type, &sent));
+
+        assert!(!is_looped(&looped[25..], &sent));
+        assert!(!is_looped(&looped, &sent[..6]));
+    }
+
+    #[test]
+    fn transmit_timestamps_are_matched_to_messages() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = socket.local_addr().unwrap();
+        set_timestamps(socket.as_raw_fd()).unwrap();
+
+        let first = [8, 0, 0, 0, 0, 0, 0, 1, 0xaa];
+        let second = [8, 0, 0, 0, 0, 0, 0, 2, 0xbb];
+        let unsent = [8, 0, 0, 0, 0, 0, 0, 3, 0xcc];
+        let before = Instant::now();
+        socket.send_to(&first, target).unwrap();
+        socket.send_to(&second, target).unwrap();
+
+        let found = tx_timestamps(socket.as_raw_fd(), &[&second, &unsent, &first]);
+        assert!(found[0].is_some());
+        assert_eq!(found[1], None);
+        assert!(found[2].is_some());
+        // timestamps are converted from the realtime clock, allow for some rounding
+        assert!(found[2].unwrap() + Duration::from_millis(5) >= before);
+        assert!(found[2].unwrap() <= Instant::now());
+
+        // the error queue is drained
+        assert_eq!(tx_timestamp(socket.as_raw_fd(), &first), None);
+    }
+}
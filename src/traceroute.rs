@@ -0,0 +1,268 @@
+use std::{fmt, net::IpAddr, time::Duration};
+
+use futures::future::join_all;
+
+use crate::{
+    error::{Result, SurgeError},
+    icmp::{IcmpError, IcmpPacket, Unreachable},
+    transport::SendOptions,
+    Pinger,
+};
+
+/// The schedule of [`Pinger::traceroute`](struct.Pinger.html#method.traceroute), like the
+/// `-f`, `-m` and `-q` options of the `traceroute` command.
+#[derive(Debug, Clone)]
+pub struct TracePlan {
+    first_hop: u8,
+    max_hops: u8,
+    probes: usize,
+    start_sequence: u16,
+}
+
+impl Default for TracePlan {
+    fn default() -> Self {
+        TracePlan {
+            first_hop: 1,
+            max_hops: 30,
+            probes: 3,
+            start_sequence: 0,
+        }
+    }
+}
+
+impl TracePlan {
+    /// Probe hops 1 to 30, three times each.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// TTL (or hop limit) of the first hop probed. (default: 1)
+    pub fn first_hop(mut self, ttl: u8) -> Self {
+        self.first_hop = ttl.max(1);
+        self
+    }
+
+    /// Give up after the hop at this TTL (or hop limit), at least the first hop so that
+    /// one hop is always probed. (default: 30)
+    pub fn max_hops(mut self, ttl: u8) -> Self {
+        self.max_hops = ttl;
+        self
+    }
+
+    /// Number of probes sent to each hop, at least 1. (default: 3)
+    pub fn probes(mut self, probes: usize) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+
+    /// Sequence number of the first probe, following ones wrap around at `u16::MAX`.
+    /// (default: 0)
+    pub fn start_sequence(mut self, seq_cnt: u16) -> Self {
+        self.start_sequence = seq_cnt;
+        self
+    }
+}
+
+/// The answer to one probe of a [`Hop`](struct.Hop.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// The destination answered with an echo reply.
+    Reply { from: IpAddr, rtt: Duration },
+    /// A router or the destination answered with an ICMP error, Time Exceeded for the
+    /// routers on the path.
+    Error {
+        from: IpAddr,
+        error: IcmpError,
+        rtt: Duration,
+    },
+    /// Nothing came back before the timeout of the `Pinger`.
+    Timeout,
+    /// The probe failed otherwise, such as a reply whose payload was rewritten on the way
+    /// (`SurgeError::CorruptPayload`). `rtt` is set if something came back.
+    Failed { rtt: Option<Duration> },
+}
+
+impl Probe {
+    fn from_result(result: Result<(IcmpPacket, Duration)>) -> Self {
+        match result {
+            Ok((packet, rtt)) => Probe::Reply {
+                from: packet.get_source(),
+                rtt,
+            },
+            Err(SurgeError::IcmpError {
+                from, error, rtt, ..
+            }) => Probe::Error { from, error, rtt },
+            Err(SurgeError::Timeout { .. }) => Probe::Timeout,
+            Err(SurgeError::CorruptPayload { rtt, .. }) => Probe::Failed { rtt: Some(rtt) },
+            Err(_) => Probe::Failed { rtt: None },
+        }
+    }
+
+    /// The address that answered the probe.
+    pub fn responder(&self) -> Option<IpAddr> {
+        match self {
+            Probe::Reply { from, .. } | Probe::Error { from, .. } => Some(*from),
+            Probe::Timeout | Probe::Failed { .. } => None,
+        }
+    }
+
+    /// Round trip time of the probe, if it was answered.
+    pub fn rtt(&self) -> Option<Duration> {
+        match self {
+            Probe::Reply { rtt, .. } | Probe::Error { rtt, .. } => Some(*rtt),
+            Probe::Failed { rtt } => *rtt,
+            Probe::Timeout => None,
+        }
+    }
+}
+
+/// The probes sent with one TTL (or hop limit).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub ttl: u8,
+    pub probes: Vec<Probe>,
+}
+
+impl Hop {
+    /// The addresses that answered at this hop, in the order of the probes. There are
+    /// several when the path changed or is load balanced.
+    pub fn responders(&self) -> Vec<IpAddr> {
+        let mut responders = Vec::new();
+        for responder in self.probes.iter().filter_map(Probe::responder) {
+            if !responders.contains(&responder) {
+                responders.push(responder);
+            }
+        }
+        responders
+    }
+}
+
+/// One line of `traceroute` output:
+///
+/// ```text
+///  3  192.0.2.1  1.234 ms  1.301 ms *
+/// ```
+impl fmt::Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:2} ", self.ttl)?;
+        let mut last = None;
+        for probe in &self.probes {
+            if let Some(responder) = probe.responder() {
+                if last != Some(responder) {
+                    write!(f, " {}", responder)?;
+                    last = Some(responder);
+                }
+            }
+            match probe {
+                Probe::Timeout => write!(f, " *")?,
+                Probe::Failed { rtt: None } => write!(f, " !?")?,
+                Probe::Failed { rtt: Some(rtt) } => write!(f, "  {:.3} ms !?", ms(*rtt))?,
+                Probe::Reply { rtt, .. } => write!(f, "  {:.3} ms", ms(*rtt))?,
+                Probe::Error { error, rtt, .. } => {
+                    write!(f, "  {:.3} ms", ms(*rtt))?;
+                    if let Some(annotation) = annotation(error) {
+                        write!(f, " {}", annotation)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn ms(rtt: Duration) -> f64 {
+    rtt.as_secs_f64() * 1000.0
+}
+
+/// The flag `traceroute` prints after the time of an ICMP error.
+fn annotation(error: &IcmpError) -> Option<String> {
+    let annotation = match error {
+        IcmpError::TimeExceeded(_) => return None,
+        IcmpError::DestinationUnreachable(unreachable) => match unreachable {
+            Unreachable::Network => "!N".to_string(),
+            Unreachable::Host => "!H".to_string(),
+            Unreachable::Protocol => "!P".to_string(),
+            Unreachable::Port => return None,
+            Unreachable::FragmentationNeeded { .. } => "!F".to_string(),
+            Unreachable::SourceRouteFailed => "!S".to_string(),
+            Unreachable::AdminProhibited => "!X".to_string(),
+            Unreachable::BeyondScope => "!N".to_string(),
+            Unreachable::Other(code) => format!("!<{}>", code),
+        },
+        _ => "!".to_string(),
+    };
+    Some(annotation)
+}
+
+/// How a trace ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceStatus {
+    /// The destination answered with an echo reply.
+    Reached,
+    /// The last hop answered with an ICMP error other than Time Exceeded, such as
+    /// Destination Unreachable.
+    Unreachable { from: IpAddr, error: IcmpError },
+    /// No probe of the last allowed hop got through.
+    MaxHops,
+}
+
+/// The result of [`Pinger::traceroute`](struct.Pinger.html#method.traceroute).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub hops: Vec<Hop>,
+    pub status: TraceStatus,
+}
+
+impl Trace {
+    /// Whether the destination answered.
+    pub fn reached(&self) -> bool {
+        self.status == TraceStatus::Reached
+    }
+}
+
+pub(crate) async fn trace(pinger: &Pinger, plan: TracePlan) -> Result<Trace> {
+    let mut hops = Vec::new();
+    let mut seq_cnt = plan.start_sequence;
+    for ttl in plan.first_hop..=plan.max_hops.max(plan.first_hop) {
+        let mut options = SendOptions::new();
+        options.hop_limit = Some(ttl);
+        let mut handles = Vec::with_capacity(plan.probes);
+        for _ in 0..plan.probes {
+            handles.push(pinger.send_with(seq_cnt, options).await?);
+            seq_cnt = seq_cnt.wrapping_add(1);
+        }
+        // like `traceroute`, a probe that failed is shown at its hop and the trace goes on
+        let probes: Vec<_> = join_all(handles)
+            .await
+            .into_iter()
+            .map(Probe::from_result)
+            .collect();
+
+        let status = if probes
+            .iter()
+            .any(|probe| matches!(probe, Probe::Reply { .. }))
+        {
+            Some(TraceStatus::Reached)
+        } else {
+            probes.iter().find_map(|probe| match probe {
+                Probe::Error { from, error, .. }
+                    if !matches!(error, IcmpError::TimeExceeded(_)) =>
+                {
+                    Some(TraceStatus::Unreachable {
+                        from: *from,
+                        error: *error,
+                    })
+                }
+                _ => None,
+            })
+        };
+        hops.push(Hop { ttl, probes });
+        if let Some(status) = status {
+            return Ok(Trace { hops, status });
+        }
+    }
+    Ok(Trace {
+        hops,
+        status: TraceStatus::MaxHops,
+    })
+}
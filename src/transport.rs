@@ -0,0 +1,145 @@
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    time::Instant,
+};
+
+use socket2::Type;
+
+use crate::ICMP;
+
+/// The future returned by the [`Transport`](trait.Transport.html) methods.
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A received message as described by [`Transport::recv_msg`](trait.Transport.html#method.recv_msg).
+///
+/// Fields the transport cannot tell are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecvMeta {
+    /// Number of bytes read.
+    pub len: usize,
+    /// The sender of the message.
+    pub addr: SocketAddr,
+    /// Hop limit (or TTL) of the IP header the message arrived in.
+    pub hop_limit: Option<u8>,
+    /// TOS (or traffic class) byte of the IP header the message arrived in: DSCP in the
+    /// upper 6 bits, ECN in the lower 2.
+    pub tos: Option<u8>,
+    /// The local address the message was sent to.
+    pub local_addr: Option<IpAddr>,
+    /// Index of the interface the message arrived on.
+    pub interface: Option<u32>,
+    /// When the kernel received the message.
+    pub timestamp: Option<Instant>,
+}
+
+impl RecvMeta {
+    /// A message of `len` bytes from `addr`, without any other information.
+    pub fn new(len: usize, addr: SocketAddr) -> Self {
+        RecvMeta {
+            len,
+            addr,
+            hop_limit: None,
+            tos: None,
+            local_addr: None,
+            interface: None,
+            timestamp: None,
+        }
+    }
+}
+
+/// Options applied to a single message by [`Transport::send_msg`](trait.Transport.html#method.send_msg),
+/// leaving the socket settings alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SendOptions {
+    /// TTL (or hop limit) of the IP header, instead of the one of the socket.
+    pub hop_limit: Option<u8>,
+    /// TOS (or traffic class) byte of the IP header, instead of the one of the socket.
+    pub tos: Option<u8>,
+}
+
+impl SendOptions {
+    /// Send with the settings of the socket.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A sent message as described by [`Transport::send_msg`](trait.Transport.html#method.send_msg).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SendMeta {
+    /// Number of bytes sent.
+    pub len: usize,
+    /// When the kernel handed the message to the network device.
+    pub timestamp: Option<Instant>,
+}
+
+impl SendMeta {
+    /// `len` bytes were sent, at an unknown time.
+    pub fn new(len: usize) -> Self {
+        SendMeta {
+            len,
+            timestamp: None,
+        }
+    }
+}
+
+/// The datagram channel a `Client` sends ICMP echo requests and receives replies over.
+///
+/// The crate uses an ICMP socket by default, implement this trait to run a
+/// [`Client`](struct.Client.html) over something else, such as the in-memory
+/// network in the [`mock`](mock/index.html) module.
+pub trait Transport: Send + Sync + 'static {
+    /// Send one ICMP message (without IP header) to `target`.
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: &'a SocketAddr) -> TransportFuture<'a, usize>;
+
+    /// Send one message like [`send_to`](#tymethod.send_to) with per-message `options`,
+    /// also telling when it left. This is what the `Client` calls.
+    ///
+    /// The default implementation calls `send_to` and does not know the time, it fails
+    /// with `ErrorKind::Unsupported` if any option is set.
+    fn send_msg<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: &'a SocketAddr,
+        options: SendOptions,
+    ) -> TransportFuture<'a, SendMeta> {
+        Box::pin(async move {
+            if options != SendOptions::default() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "per-message options are not supported by this transport",
+                ));
+            }
+            self.send_to(buf, target).await.map(SendMeta::new)
+        })
+    }
+
+    /// Receive one message, returning the number of bytes read and the sender.
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)>;
+
+    /// Receive one message along with what the IP header said about it. This is what the
+    /// `Client` calls, IPv6 sockets need it because the kernel never hands out the IPv6
+    /// header.
+    ///
+    /// The default implementation calls [`recv_from`](#tymethod.recv_from) and knows
+    /// nothing beyond the sender.
+    fn recv_msg<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, RecvMeta> {
+        Box::pin(async move {
+            let (len, addr) = self.recv_from(buf).await?;
+            Ok(RecvMeta::new(len, addr))
+        })
+    }
+
+    /// `Type::RAW` if received IPv4 messages start with the IP header and the ICMP
+    /// identifier is left untouched, `Type::DGRAM` if neither is the case.
+    fn sock_type(&self) -> Type;
+
+    /// The family of the messages, `ICMP::V4` or `ICMP::V6`.
+    fn kind(&self) -> ICMP;
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the integration tests, each of which uses only some of them.
+#![allow(dead_code)]
+
+use std::net::IpAddr;
+
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{Client, DualStackClient, ICMP};
+
+pub fn addr(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+/// A mock network with `hosts` and a client of the `kind` family over it.
+pub fn mock_client<'a, I>(kind: ICMP, hosts: I) -> (MockNetwork, Client)
+where
+    I: IntoIterator<Item = (&'a str, MockHost)>,
+{
+    let network = MockNetwork::new();
+    for (host, mock) in hosts {
+        network.host(addr(host), mock).unwrap();
+    }
+    let client = Client::with_transport(network.transport(kind));
+    (network, client)
+}
+
+/// A dual stack client over both families of `network`.
+pub fn dual_stack(network: &MockNetwork) -> DualStackClient {
+    DualStackClient::with_transports(network.transport(ICMP::V4), network.transport(ICMP::V6))
+}
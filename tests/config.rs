@@ -0,0 +1,72 @@
+#[cfg(target_os = "linux")]
+use std::io;
+
+#[cfg(target_os = "linux")]
+use socket2::Type;
+use surge_ping::Config;
+#[cfg(target_os = "linux")]
+use surge_ping::{Client, SocketOptionError, ICMP};
+
+#[test]
+fn builder_sets_routing_options() {
+    let config = Config::builder()
+        .vrf("blue")
+        .mark(42)
+        .freebind(true)
+        .transparent(true)
+        .build();
+    assert_eq!(config.vrf.as_deref(), Some("blue"));
+    assert_eq!(config.mark, Some(42));
+    assert!(config.freebind);
+    assert!(config.transparent);
+    assert_eq!(config.interface, None);
+
+    let config = Config::default();
+    assert_eq!(config.vrf, None);
+    assert_eq!(config.mark, None);
+    assert!(!config.freebind);
+    assert!(!config.transparent);
+}
+
+/// The option named by the error of `Client::new`, `None` if no socket could be opened at
+/// all (no `CAP_NET_RAW` and no ping sockets allowed).
+#[cfg(target_os = "linux")]
+async fn failed_option(config: Config) -> Option<(&'static str, io::ErrorKind)> {
+    let e = match Client::new(&config).await {
+        Ok(_) => panic!("client created"),
+        Err(e) => e,
+    };
+    let kind = e.kind();
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<SocketOptionError>())
+    {
+        Some(failed) => Some((failed.option, kind)),
+        None if kind == io::ErrorKind::PermissionDenied => None,
+        None => panic!("unnamed error {:?}", e),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn failing_option_is_named() {
+    let unknown_vrf = Config::builder()
+        .sock_type_hint(Type::DGRAM)
+        .vrf("surge-no-such-vrf")
+        .build();
+    if let Some((option, kind)) = failed_option(unknown_vrf).await {
+        assert_eq!(option, "SO_BINDTODEVICE");
+        assert_ne!(kind, io::ErrorKind::InvalidInput);
+    }
+
+    let both = Config::builder()
+        .sock_type_hint(Type::DGRAM)
+        .kind(ICMP::V6)
+        .vrf("blue")
+        .interface("lo")
+        .build();
+    if let Some((option, kind)) = failed_option(both).await {
+        assert_eq!(option, "SO_BINDTODEVICE");
+        assert_eq!(kind, io::ErrorKind::InvalidInput);
+    }
+}
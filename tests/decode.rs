@@ -0,0 +1,105 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use proptest::prelude::*;
+use socket2::Type;
+use surge_ping::{IcmpPacket, Icmpv4Packet, Icmpv6Packet, SurgeError};
+
+// ipv4 header(20) + time exceeded(8) + quoted ipv4 header(20) + quoted echo request(8)
+fn time_exceeded_v4() -> Vec<u8> {
+    let mut buf = vec![
+        0x45, 0, 0, 56, 0, 0, 0, 0, 64, 1, 0, 0, 10, 0, 0, 254, 10, 0, 0, 100,
+    ];
+    buf.extend_from_slice(&[11, 0, 0, 0, 0, 0, 0, 0]);
+    buf.extend_from_slice(&[
+        0x45, 0, 0, 84, 0, 0, 0, 0, 1, 1, 0, 0, 10, 0, 0, 100, 10, 0, 0, 1,
+    ]);
+    buf.extend_from_slice(&[8, 0, 0, 0, 0x12, 0x34, 0, 7]);
+    buf
+}
+
+// icmpv6 destination unreachable(8) + quoted ipv6 header(40) + quoted echo request(8)
+fn unreachable_v6() -> Vec<u8> {
+    let mut buf = vec![1, 3, 0, 0, 0, 0, 0, 0];
+    buf.extend_from_slice(&[0x60, 0, 0, 0, 0, 8, 58, 64]);
+    buf.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+    buf.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+    buf.extend_from_slice(&[128, 0, 0, 0, 0x12, 0x34, 0, 7]);
+    buf
+}
+
+#[test]
+fn decodes_quoted_request() {
+    let packet = Icmpv4Packet::decode(&time_exceeded_v4()).unwrap();
+    assert_eq!(packet.get_identifier(), 0x1234);
+    assert_eq!(packet.get_sequence(), 7);
+    assert_eq!(packet.get_real_dest(), Ipv4Addr::new(10, 0, 0, 1));
+
+    let packet = Icmpv6Packet::decode(&unreachable_v6(), Ipv6Addr::LOCALHOST).unwrap();
+    assert_eq!(packet.get_identifier(), 0x1234);
+    assert_eq!(packet.get_sequence(), 7);
+    assert_eq!(
+        packet.get_real_dest(),
+        "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+    );
+}
+
+#[test]
+fn quoted_request_is_checked_against_the_request() {
+    let packet = IcmpPacket::V4(Icmpv4Packet::decode(&time_exceeded_v4()).unwrap());
+    let destination = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    assert!(packet.check_reply_packet(destination, 7, 0x1234));
+    assert!(!packet.check_reply_packet(destination, 7, 0x4321));
+    assert!(!packet.check_reply_packet(destination, 8, 0x1234));
+    assert!(!packet.check_reply_packet(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 7, 0x1234));
+}
+
+#[test]
+fn truncated_packets_are_too_short() {
+    for (full, decode) in [
+        (
+            time_exceeded_v4(),
+            Box::new(|buf: &[u8]| Icmpv4Packet::decode(buf).map(drop))
+                as Box<dyn Fn(&[u8]) -> Result<(), SurgeError>>,
+        ),
+        (
+            unreachable_v6(),
+            Box::new(|buf: &[u8]| Icmpv6Packet::decode(buf, Ipv6Addr::LOCALHOST).map(drop)),
+        ),
+    ] {
+        for len in 0..full.len() {
+            match decode(&full[..len]) {
+                Err(SurgeError::MalformedPacket(e)) => assert!(
+                    e.to_string().starts_with("payload too short"),
+                    "len {}: {}",
+                    len,
+                    e
+                ),
+                other => panic!("len {}: unexpected {:?}", len, other),
+            }
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn decode_v4_raw_never_panics(buf in proptest::collection::vec(any::<u8>(), 0..128)) {
+        let _ = Icmpv4Packet::decode(&buf);
+    }
+
+    #[test]
+    fn decode_v4_dgram_never_panics(buf in proptest::collection::vec(any::<u8>(), 0..128)) {
+        let _ = Icmpv4Packet::decode_with(&buf, Type::DGRAM, Ipv4Addr::LOCALHOST);
+    }
+
+    #[test]
+    fn decode_v6_never_panics(buf in proptest::collection::vec(any::<u8>(), 0..128)) {
+        let _ = Icmpv6Packet::decode(&buf, Ipv6Addr::LOCALHOST);
+    }
+
+    #[test]
+    fn decode_mutated_v4_never_panics(index in 0usize..56, byte in any::<u8>(), len in 0usize..=56) {
+        let mut buf = time_exceeded_v4();
+        buf[index] = byte;
+        let _ = Icmpv4Packet::decode(&buf[..len]);
+    }
+}
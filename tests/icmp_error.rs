@@ -0,0 +1,72 @@
+use surge_ping::mock::MockHost;
+use surge_ping::{IcmpError, PingStatistics, SurgeError, TimeExceeded, Unreachable, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[tokio::test]
+async fn host_unreachable_v4() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().error(addr("10.0.0.254"), 3, 1))],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let err = pinger.ping(4).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "From 10.0.0.254 icmp_seq=4 Destination Host Unreachable"
+    );
+    match err {
+        SurgeError::IcmpError {
+            seq, from, error, ..
+        } => {
+            assert_eq!(seq, 4);
+            assert_eq!(from, addr("10.0.0.254"));
+            assert_eq!(error, IcmpError::DestinationUnreachable(Unreachable::Host));
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn time_exceeded_v6() {
+    let (_, client) = mock_client(
+        ICMP::V6,
+        [(
+            "2001:db8::1",
+            MockHost::new().error(addr("2001:db8::fe"), 3, 0),
+        )],
+    );
+    let pinger = client.pinger(addr("2001:db8::1")).await;
+
+    match pinger.ping(2).await {
+        Err(SurgeError::IcmpError {
+            seq: 2,
+            from,
+            error: IcmpError::TimeExceeded(TimeExceeded::Transit),
+            ..
+        }) => assert_eq!(from, addr("2001:db8::fe")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn statistics_count_errors_by_type() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().error(addr("10.0.0.254"), 3, 13))],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let mut stats = PingStatistics::new();
+    for seq in 0..3 {
+        stats.record(&pinger.ping(seq).await);
+    }
+    assert_eq!(stats.received(), 0);
+    assert_eq!(stats.errors(), 3);
+    let prohibited = IcmpError::DestinationUnreachable(Unreachable::AdminProhibited);
+    assert_eq!(stats.icmp_errors().get(&prohibited), Some(&3));
+    assert!(stats.to_string().contains(", +3 errors, 100% packet loss"));
+}
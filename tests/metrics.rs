@@ -0,0 +1,189 @@
+#![cfg(feature = "metrics")]
+
+use std::time::Duration;
+
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{Client, Config, Metrics, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+fn line<'a>(text: &'a str, series: &str) -> &'a str {
+    text.lines()
+        .find(|line| line.rsplit_once(' ').map(|(name, _)| name) == Some(series))
+        .unwrap_or_else(|| panic!("no {} in\n{}", series, text))
+}
+
+fn value(text: &str, series: &str) -> String {
+    line(text, series).rsplit_once(' ').unwrap().1.to_string()
+}
+
+#[tokio::test]
+async fn per_target_metrics() {
+    let network = MockNetwork::new();
+    network
+        .host(
+            addr("10.0.0.1"),
+            MockHost::new().delay(Duration::from_millis(30)),
+        )
+        .unwrap();
+    network
+        .host(
+            addr("10.0.0.2"),
+            MockHost::new().error(addr("10.0.0.254"), 3, 1),
+        )
+        .unwrap();
+    let metrics =
+        Metrics::with_buckets(vec![Duration::from_millis(10), Duration::from_millis(100)]);
+    let config = Config::builder().metrics(metrics.clone()).build();
+    let client = Client::with_transport_config(network.transport(ICMP::V4), &config);
+
+    for host in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+        let mut pinger = client.pinger(addr(host)).await;
+        pinger.timeout(Duration::from_millis(100));
+        for seq in 0..2 {
+            let _ = pinger.ping(seq).await;
+        }
+    }
+
+    let text = metrics.render();
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_requests_sent_total{target="10.0.0.1"}"#
+        ),
+        "2"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_replies_received_total{target="10.0.0.1"}"#
+        ),
+        "2"
+    );
+    assert_eq!(
+        value(&text, r#"surge_ping_timeouts_total{target="10.0.0.3"}"#),
+        "2"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_icmp_errors_total{target="10.0.0.2",type="destination_unreachable",reason="host"}"#
+        ),
+        "2"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_rtt_seconds_bucket{target="10.0.0.1",le="0.01"}"#
+        ),
+        "0"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_rtt_seconds_bucket{target="10.0.0.1",le="0.1"}"#
+        ),
+        "2"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_rtt_seconds_bucket{target="10.0.0.1",le="+Inf"}"#
+        ),
+        "2"
+    );
+    assert_eq!(
+        value(&text, r#"surge_ping_rtt_seconds_count{target="10.0.0.1"}"#),
+        "2"
+    );
+    assert_eq!(value(&text, "surge_ping_packets_delivered_total"), "4");
+    assert_eq!(
+        text.matches("# TYPE surge_ping_rtt_seconds histogram")
+            .count(),
+        1
+    );
+
+    assert!(metrics.remove(addr("10.0.0.3")));
+    assert!(!metrics.render().contains(r#"target="10.0.0.3""#));
+}
+
+#[tokio::test]
+async fn abandoned_requests_are_unmatched() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().delay(Duration::from_millis(20)))],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    drop(pinger.send(0).await.unwrap());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let text = client.metrics().render();
+    assert_eq!(value(&text, "surge_ping_packets_received_total"), "1");
+    assert_eq!(value(&text, "surge_ping_packets_delivered_total"), "0");
+    assert_eq!(value(&text, "surge_ping_packets_unmatched_total"), "1");
+    assert_eq!(value(&text, "surge_ping_packets_dropped_total"), "0");
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_requests_sent_total{target="10.0.0.1"}"#
+        ),
+        "1"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_replies_received_total{target="10.0.0.1"}"#
+        ),
+        "0"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_requests_abandoned_total{target="10.0.0.1"}"#
+        ),
+        "1"
+    );
+}
+
+#[tokio::test]
+async fn corrupt_replies_are_counted() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().corrupt(vec![10]))]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.verify_payload(true);
+
+    assert!(pinger.ping(0).await.is_err());
+    let text = client.metrics().render();
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_corrupt_replies_total{target="10.0.0.1"}"#
+        ),
+        "1"
+    );
+    assert_eq!(
+        value(
+            &text,
+            r#"surge_ping_replies_received_total{target="10.0.0.1"}"#
+        ),
+        "0"
+    );
+}
+
+#[tokio::test]
+async fn dropped_clients_keep_their_counts() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    let metrics = Metrics::new();
+    let config = Config::builder().metrics(metrics.clone()).build();
+
+    for seq in 0..3 {
+        let client = Client::with_transport_config(network.transport(ICMP::V4), &config);
+        let pinger = client.pinger(addr("10.0.0.1")).await;
+        pinger.ping(seq).await.unwrap();
+    }
+    // let the receive tasks of the dropped clients end
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(metrics.dispatch_stats().delivered, 3);
+}
@@ -0,0 +1,454 @@
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use socket2::Type;
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{
+    Client, IcmpPacket, RecvMeta, SendOptions, SurgeError, Transport, TransportFuture, ICMP,
+};
+use tokio::sync::{mpsc, Mutex};
+
+mod common;
+
+use common::{addr, dual_stack, mock_client};
+
+#[tokio::test]
+async fn echo_reply_v4() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().ttl(57))]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.ident(7);
+
+    match pinger.ping(3).await {
+        Ok((IcmpPacket::V4(packet), _)) => {
+            assert_eq!(
+                packet.get_source(),
+                "10.0.0.1".parse::<std::net::Ipv4Addr>().unwrap()
+            );
+            assert_eq!(packet.get_ttl(), 57);
+            assert_eq!(packet.get_identifier(), 7);
+            assert_eq!(packet.get_sequence(), 3);
+            assert_eq!(packet.get_size(), 64);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn echo_reply_v4_dgram() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    let client = Client::with_transport(network.transport_with_type(ICMP::V4, Type::DGRAM));
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let (packet, _) = pinger.ping(1).await.unwrap();
+    assert!(matches!(packet, IcmpPacket::V4(p) if p.get_sequence() == 1));
+}
+
+#[tokio::test]
+async fn echo_reply_v6() {
+    let (_, client) = mock_client(ICMP::V6, [("2001:db8::1", MockHost::new())]);
+    let pinger = client.pinger(addr("2001:db8::1")).await;
+
+    let (packet, _) = pinger.ping(9).await.unwrap();
+    assert!(matches!(packet, IcmpPacket::V6(p) if p.get_sequence() == 9));
+}
+
+#[tokio::test]
+async fn reply_v6_reports_header_fields() {
+    let (_, client) = mock_client(ICMP::V6, [("2001:db8::1", MockHost::new().ttl(57))]);
+    let pinger = client.pinger(addr("2001:db8::1")).await;
+
+    match pinger.ping(0).await.unwrap() {
+        (IcmpPacket::V6(packet), _) => {
+            assert_eq!(packet.get_max_hop_limit(), 57);
+            assert_eq!(
+                packet.get_source(),
+                "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+            );
+            assert_eq!(packet.get_destination(), Ipv6Addr::LOCALHOST);
+        }
+        (packet, _) => panic!("unexpected {:?}", packet),
+    }
+}
+
+#[tokio::test]
+async fn delay_is_measured() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().delay(Duration::from_millis(50)))],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let (_, rtt) = pinger.ping(0).await.unwrap();
+    assert!(rtt >= Duration::from_millis(50), "rtt {:?}", rtt);
+}
+
+#[tokio::test]
+async fn lost_and_unknown_hosts_time_out() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().loss(1.0))]);
+
+    for host in &["10.0.0.1", "10.0.0.2"] {
+        let mut pinger = client.pinger(addr(host)).await;
+        pinger.timeout(Duration::from_millis(100));
+        assert!(matches!(
+            pinger.ping(5).await,
+            Err(SurgeError::Timeout { seq: 5 })
+        ));
+    }
+}
+
+#[tokio::test]
+async fn duplicates_do_not_confuse_later_pings() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().duplicates(2))]);
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    for seq in 0..3 {
+        let (packet, _) = pinger.ping(seq).await.unwrap();
+        assert!(matches!(packet, IcmpPacket::V4(p) if p.get_sequence() == seq));
+    }
+}
+
+#[tokio::test]
+async fn dispatch_stats_count_duplicates() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().duplicates(2))]);
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    for seq in 0..3 {
+        pinger.ping(seq).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let stats = client.dispatch_stats();
+    assert_eq!(stats.received, 9);
+    assert_eq!(stats.delivered, 3);
+    assert_eq!(stats.unmatched, 6);
+    assert_eq!(stats.malformed, 0);
+}
+
+#[tokio::test]
+async fn idle_pinger_does_not_block_others() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new()), ("10.0.0.2", MockHost::new())],
+    );
+    let idle = client.pinger(addr("10.0.0.1")).await;
+    let busy = client.pinger(addr("10.0.0.2")).await;
+
+    // Replies pile up for requests nobody polls yet.
+    let mut handles = Vec::new();
+    for seq in 0..100 {
+        handles.push(idle.send(seq).await.unwrap());
+    }
+    for seq in 0..1000 {
+        busy.ping(seq).await.unwrap();
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn pingers_share_one_client() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new()), ("10.0.0.2", MockHost::new())],
+    );
+    let first = client.pinger(addr("10.0.0.1")).await;
+    let second = client.pinger(addr("10.0.0.2")).await;
+
+    let (a, b) = tokio::join!(first.ping(0), second.ping(0));
+    assert!(matches!(a, Ok((IcmpPacket::V4(p), _)) if p.get_source().octets() == [10, 0, 0, 1]));
+    assert!(matches!(b, Ok((IcmpPacket::V4(p), _)) if p.get_source().octets() == [10, 0, 0, 2]));
+}
+
+#[tokio::test]
+async fn many_requests_in_flight() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new().delay(Duration::from_millis(200)),
+        )],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let start = std::time::Instant::now();
+    let mut handles = Vec::new();
+    for seq in 0..10 {
+        handles.push(pinger.send(seq).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    for (seq, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.sequence(), seq as u16);
+        let (packet, rtt) = handle.await.unwrap();
+        assert!(matches!(packet, IcmpPacket::V4(p) if p.get_sequence() == seq as u16));
+        assert!(rtt >= Duration::from_millis(200));
+    }
+    assert!(start.elapsed() < Duration::from_millis(1000));
+}
+
+#[tokio::test]
+async fn ttl_is_per_pinger() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new().path(vec![addr("192.0.2.1"), addr("192.0.2.2")]),
+        )],
+    );
+    let mut short = client.pinger(addr("10.0.0.1")).await;
+    short.ttl(2);
+    let long = client.pinger(addr("10.0.0.1")).await;
+
+    let (short, long) = tokio::join!(short.ping(0), long.ping(0));
+    assert!(matches!(
+        short,
+        Err(SurgeError::IcmpError { from, .. }) if from == addr("192.0.2.2")
+    ));
+    assert!(long.is_ok());
+}
+
+#[tokio::test]
+async fn ttl_per_request() {
+    let (_, client) = mock_client(
+        ICMP::V6,
+        [(
+            "2001:db8::1",
+            MockHost::new().path(vec![addr("2001:db8:ff::1")]),
+        )],
+    );
+    let mut pinger = client.pinger(addr("2001:db8::1")).await;
+    pinger.ttl(1);
+
+    let mut options = SendOptions::new();
+    options.hop_limit = Some(2);
+    assert!(pinger.ping_with(0, options).await.is_ok());
+    assert!(matches!(
+        pinger.ping(1).await,
+        Err(SurgeError::IcmpError { from, .. }) if from == addr("2001:db8:ff::1")
+    ));
+}
+
+#[tokio::test]
+async fn dropping_a_clone_keeps_client_running() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    drop(client.clone());
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    assert!(pinger.ping(0).await.is_ok());
+}
+
+#[tokio::test]
+async fn dual_stack_picks_family() {
+    let network = MockNetwork::new();
+    let hosts = [addr("10.0.0.1"), addr("2001:db8::1"), addr("10.0.0.2")];
+    for host in &hosts {
+        network.host(*host, MockHost::new()).unwrap();
+    }
+    let client = dual_stack(&network);
+
+    for host in &hosts {
+        let pinger = client.pinger(*host).await;
+        let (packet, _) = pinger.ping(0).await.unwrap();
+        assert_eq!(packet.get_source(), *host);
+    }
+}
+
+#[tokio::test]
+async fn empty_payload() {
+    let (network, client_v4) = mock_client(
+        ICMP::V4,
+        [
+            ("10.0.0.1", MockHost::new()),
+            ("2001:db8::1", MockHost::new()),
+        ],
+    );
+    let client_v6 = Client::with_transport(network.transport_with_type(ICMP::V6, Type::DGRAM));
+
+    let mut pinger = client_v4.pinger(addr("10.0.0.1")).await;
+    pinger.size(0);
+    let (packet, _) = pinger.ping(0).await.unwrap();
+    assert!(matches!(packet, IcmpPacket::V4(p) if p.get_size() == 8));
+
+    let mut pinger = client_v6.pinger(addr("2001:db8::1")).await;
+    pinger.size(0);
+    let (packet, _) = pinger.ping(0).await.unwrap();
+    assert!(matches!(packet, IcmpPacket::V6(p) if p.get_size() == 8));
+}
+
+#[tokio::test]
+async fn oversized_requests_fail() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+
+    // the IPv4 header and the ICMP header leave 65507 bytes of payload
+    pinger.size(65507);
+    assert!(pinger.ping(0).await.is_ok());
+    pinger.size(65508);
+    match pinger.ping(1).await {
+        Err(SurgeError::IOError(e)) => assert!(e.raw_os_error().is_some()),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn same_destination_dgram() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    let client = Client::with_transport(network.transport_with_type(ICMP::V4, Type::DGRAM));
+    let mut first = client.pinger(addr("10.0.0.1")).await;
+    first.timeout(Duration::from_millis(200));
+    let mut second = client.pinger(addr("10.0.0.1")).await;
+    second.timeout(Duration::from_millis(200));
+
+    // the kernel gives both the same identifier, the copy in the payload tells them apart
+    let (first, second) = tokio::join!(first.ping(3), second.ping(3));
+    assert!(first.is_ok());
+    assert!(second.is_ok());
+}
+
+#[tokio::test]
+async fn changed_ident() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(200));
+
+    pinger.ident(7);
+    let (packet, _) = pinger.ping(0).await.unwrap();
+    assert!(matches!(packet, IcmpPacket::V4(p) if p.get_identifier() == 7));
+    pinger.ident = 8;
+    assert!(pinger.ping(1).await.is_ok());
+}
+
+#[tokio::test]
+async fn taken_ident_is_refused() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let first = client.pinger(addr("10.0.0.1")).await;
+    let mut second = client.pinger(addr("10.0.0.1")).await;
+    assert_ne!(first.ident, second.ident);
+
+    second.ident(first.ident);
+    match second.ping(0).await {
+        Err(SurgeError::IOError(e)) => assert_eq!(e.kind(), io::ErrorKind::AddrInUse),
+        other => panic!("unexpected {:?}", other),
+    }
+    // the first pinger keeps its replies
+    assert!(first.ping(1).await.is_ok());
+}
+
+#[tokio::test]
+async fn informational_v6_messages_are_unmatched() {
+    let network = MockNetwork::new();
+    // a Router Advertisement quoting the request would decode like an error
+    network
+        .host(
+            addr("2001:db8::1"),
+            MockHost::new().error(addr("fe80::1"), 134, 0),
+        )
+        .unwrap();
+    let client = Client::with_transport(network.transport(ICMP::V6));
+    let mut pinger = client.pinger(addr("2001:db8::1")).await;
+    pinger.timeout(Duration::from_millis(50));
+
+    assert!(matches!(
+        pinger.ping(0).await,
+        Err(SurgeError::Timeout { .. })
+    ));
+    let stats = client.dispatch_stats();
+    assert_eq!(stats.received, 1);
+    assert_eq!(stats.unmatched, 1);
+    assert_eq!(stats.malformed, 0);
+}
+
+#[tokio::test]
+async fn routers_of_another_family_are_refused() {
+    let network = MockNetwork::new();
+    let hosts = [
+        MockHost::new().error(addr("2001:db8::fe"), 3, 1),
+        MockHost::new().path(vec![addr("10.0.0.254"), addr("2001:db8::fe")]),
+        MockHost::new().mtu(1400, Some(addr("2001:db8::fe"))),
+    ];
+    for host in hosts {
+        let err = network.host(addr("10.0.0.1"), host).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    let err = network
+        .host(
+            addr("2001:db8::1"),
+            MockHost::new().path(vec![addr("10.0.0.254")]),
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    // the refused hosts were not added and do not answer
+    let client = Client::with_transport(network.transport(ICMP::V4));
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(50));
+    assert!(matches!(
+        pinger.ping(1).await,
+        Err(SurgeError::Timeout { .. })
+    ));
+}
+
+/// A RAW ICMPv6 transport answering every request like another process pinging the same
+/// host would see it answered: same sequence number, its own identifier and payload.
+struct ForeignReplies {
+    tx: mpsc::UnboundedSender<(Vec<u8>, SocketAddr)>,
+    rx: Mutex<mpsc::UnboundedReceiver<(Vec<u8>, SocketAddr)>>,
+}
+
+impl Transport for ForeignReplies {
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: &'a SocketAddr) -> TransportFuture<'a, usize> {
+        let mut reply = buf.to_vec();
+        reply[0] = 129;
+        reply[4..6].copy_from_slice(&3781u16.to_be_bytes());
+        for byte in &mut reply[8..] {
+            *byte = 0xa5;
+        }
+        let _ = self.tx.send((reply, *target));
+        Box::pin(async move { Ok(buf.len()) })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(async move {
+            let (reply, addr) = self.rx.lock().await.recv().await.unwrap();
+            buf[..reply.len()].copy_from_slice(&reply);
+            Ok((reply.len(), addr))
+        })
+    }
+
+    fn recv_msg<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, RecvMeta> {
+        Box::pin(async move {
+            let (len, addr) = self.recv_from(buf).await?;
+            Ok(RecvMeta::new(len, addr))
+        })
+    }
+
+    fn sock_type(&self) -> Type {
+        Type::RAW
+    }
+
+    fn kind(&self) -> ICMP {
+        ICMP::V6
+    }
+}
+
+#[tokio::test]
+async fn foreign_ident_is_unmatched_raw() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let client = Client::with_transport(ForeignReplies {
+        tx,
+        rx: Mutex::new(rx),
+    });
+    let mut pinger = client.pinger(addr("2001:db8::1")).await;
+    pinger.ident(61840).timeout(Duration::from_millis(50));
+
+    assert!(matches!(
+        pinger.ping(1).await,
+        Err(SurgeError::Timeout { seq: 1 })
+    ));
+    let stats = client.dispatch_stats();
+    assert_eq!(stats.received, 1);
+    assert_eq!(stats.delivered, 0);
+    assert_eq!(stats.unmatched, 1);
+}
@@ -0,0 +1,142 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use surge_ping::mock::MockHost;
+use surge_ping::{Monitor, MonitorEvent, MonitorPlan, TargetState, ICMP};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::timeout;
+
+mod common;
+
+use common::{addr, mock_client};
+
+fn plan() -> MonitorPlan {
+    MonitorPlan::new()
+        .interval(Duration::from_millis(10))
+        .timeout(Duration::from_millis(20))
+        .rise(2)
+        .fall(2)
+}
+
+async fn next(events: &mut UnboundedReceiver<MonitorEvent>) -> (TargetState, TargetState) {
+    let event = timeout(Duration::from_secs(2), events.recv())
+        .await
+        .expect("no event")
+        .unwrap();
+    (event.previous, event.state)
+}
+
+#[tokio::test]
+async fn up_and_down() {
+    let (network, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let (mut monitor, mut events) = Monitor::new(client, plan());
+    monitor.add(addr("10.0.0.1")).await;
+    assert_eq!(monitor.state(addr("10.0.0.1")), Some(TargetState::Unknown));
+
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Unknown, TargetState::Up)
+    );
+    assert_eq!(monitor.state(addr("10.0.0.1")), Some(TargetState::Up));
+
+    network.remove_host(addr("10.0.0.1"));
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Up, TargetState::Down)
+    );
+
+    assert!(monitor.remove(addr("10.0.0.1")));
+    assert_eq!(monitor.state(addr("10.0.0.1")), None);
+}
+
+#[tokio::test]
+async fn slow_replies_are_unhealthy() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().delay(Duration::from_millis(30)))],
+    );
+    let plan = plan()
+        .timeout(Duration::from_millis(100))
+        .max_rtt(Duration::from_millis(10));
+    let (mut monitor, mut events) = Monitor::new(client, plan);
+    monitor.add(addr("10.0.0.1")).await;
+
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Unknown, TargetState::Down)
+    );
+}
+
+#[tokio::test]
+async fn flapping_is_detected() {
+    let (network, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let plan = plan().flapping(2, Duration::from_secs(10));
+    let (mut monitor, mut events) = Monitor::new(client, plan);
+    monitor.add(addr("10.0.0.1")).await;
+
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Unknown, TargetState::Up)
+    );
+    network.remove_host(addr("10.0.0.1"));
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Up, TargetState::Down)
+    );
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Down, TargetState::Flapping)
+    );
+    // further changes are not reported while flapping
+    network.remove_host(addr("10.0.0.1"));
+    assert!(timeout(Duration::from_millis(200), events.recv())
+        .await
+        .is_err());
+    assert_eq!(monitor.state(addr("10.0.0.1")), Some(TargetState::Flapping));
+}
+
+#[tokio::test]
+async fn callback_receives_events() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let mut monitor = Monitor::with_callback(client, plan(), move |event: &MonitorEvent| {
+        sink.lock().unwrap().push((event.target, event.state));
+    });
+    monitor.add(addr("10.0.0.1")).await;
+    monitor.add(addr("10.0.0.2")).await;
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort_by_key(|(target, _)| *target);
+    assert_eq!(
+        seen,
+        vec![
+            (addr("10.0.0.1"), TargetState::Up),
+            (addr("10.0.0.2"), TargetState::Down)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn removed_targets_stay_removed() {
+    let (network, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().delay(Duration::from_millis(5)))],
+    );
+    let (mut monitor, mut events) = Monitor::new(client, plan().interval(Duration::ZERO));
+    monitor.add(addr("10.0.0.1")).await;
+    assert_eq!(
+        next(&mut events).await,
+        (TargetState::Unknown, TargetState::Up)
+    );
+
+    // with probes in flight
+    network.remove_host(addr("10.0.0.1"));
+    assert!(monitor.remove(addr("10.0.0.1")));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(monitor.state(addr("10.0.0.1")), None);
+    assert!(monitor.states().is_empty());
+    assert!(events.try_recv().is_err());
+}
@@ -0,0 +1,102 @@
+use surge_ping::mock::MockHost;
+use surge_ping::{Client, PayloadPattern, SurgeError, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[test]
+fn parse_patterns() {
+    assert_eq!(
+        "ff00".parse::<PayloadPattern>().unwrap(),
+        PayloadPattern::Repeat(vec![0xff, 0x00])
+    );
+    assert_eq!(
+        "random".parse::<PayloadPattern>().unwrap(),
+        PayloadPattern::Random
+    );
+    for invalid in ["", "f", "zz", "00112233445566778899aabbccddeeff00"] {
+        assert!(invalid.parse::<PayloadPattern>().is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn patterns_are_echoed() {
+    let (network, v4) = mock_client(
+        ICMP::V4,
+        [
+            ("10.0.0.1", MockHost::new()),
+            ("2001:db8::1", MockHost::new()),
+        ],
+    );
+    let v6 = Client::with_transport(network.transport(ICMP::V6));
+
+    let mut pinger = v4.pinger(addr("10.0.0.1")).await;
+    pinger
+        .size(8)
+        .pattern(PayloadPattern::Repeat(vec![0xab, 0xcd, 0xef]));
+    let (reply, _) = pinger.ping(0).await.unwrap();
+    let ident = pinger.ident.to_be_bytes();
+    assert_eq!(
+        reply.get_payload(),
+        [ident[0], ident[1], 0xab, 0xcd, 0xef, 0xab, 0xcd, 0xef]
+    );
+
+    let mut pinger = v6.pinger(addr("2001:db8::1")).await;
+    pinger.size(300).pattern(PayloadPattern::Incrementing);
+    let (reply, _) = pinger.ping(0).await.unwrap();
+    assert_eq!(reply.get_payload().len(), 300);
+    assert_eq!(reply.get_payload()[2..6], [0, 1, 2, 3]);
+    assert_eq!(reply.get_payload()[258], 0);
+}
+
+#[tokio::test]
+async fn pattern_without_token() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let mut first = client.pinger(addr("10.0.0.1")).await;
+    first
+        .size(4)
+        .pattern(PayloadPattern::Repeat(vec![0xab, 0xcd]))
+        .payload_token(false)
+        .verify_payload(true);
+    let mut second = client.pinger(addr("10.0.0.1")).await;
+    second.size(4).payload_token(false);
+
+    // RAW replies are matched by their identifier
+    let (first, second) = tokio::join!(first.ping(0), second.ping(0));
+    let (reply, _) = first.unwrap();
+    assert_eq!(reply.get_payload(), [0xab, 0xcd, 0xab, 0xcd]);
+    let (reply, _) = second.unwrap();
+    assert_eq!(reply.get_payload(), [0; 4]);
+}
+
+#[tokio::test]
+async fn verify_payload() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [
+            ("10.0.0.1", MockHost::new()),
+            ("10.0.0.2", MockHost::new().corrupt(vec![10, 40, 100])),
+        ],
+    );
+
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.pattern(PayloadPattern::Random).verify_payload(true);
+    for seq in 0..5 {
+        assert!(pinger.ping(seq).await.is_ok());
+    }
+
+    let mut pinger = client.pinger(addr("10.0.0.2")).await;
+    pinger.pattern(PayloadPattern::Random);
+    assert!(pinger.ping(0).await.is_ok());
+    pinger.verify_payload(true);
+    match pinger.ping(1).await {
+        Err(SurgeError::CorruptPayload { seq, mismatch, .. }) => {
+            assert_eq!(seq, 1);
+            assert_eq!(mismatch.offsets, vec![10, 40]);
+            assert_eq!(mismatch.differing, 2);
+            assert_eq!(mismatch.to_string(), "2 bytes differ at offset 10, 40");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
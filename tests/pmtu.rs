@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use surge_ping::mock::MockHost;
+use surge_ping::{IcmpError, MtuPlan, SurgeError, Unreachable, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[tokio::test]
+async fn fragmentation_needed_v4() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new().mtu(1400, Some(addr("192.0.2.1"))),
+        )],
+    );
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let path = pinger.discover_mtu(MtuPlan::new()).await.unwrap();
+    assert_eq!(path.mtu, 1400);
+    assert_eq!(path.reported_by, Some(addr("192.0.2.1")));
+    // the minimum, the maximum and the reported MTU
+    assert_eq!(path.probes, 3);
+    assert_eq!(pinger.size, 56);
+
+    // the errors carry the MTU for plain pings as well
+    pinger.size(1500 - 28);
+    match pinger.ping(0).await {
+        Err(SurgeError::IcmpError { error, .. }) => assert_eq!(
+            error,
+            IcmpError::DestinationUnreachable(Unreachable::FragmentationNeeded {
+                next_hop_mtu: 1400
+            })
+        ),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn packet_too_big_v6() {
+    let (_, client) = mock_client(
+        ICMP::V6,
+        [(
+            "2001:db8::1",
+            MockHost::new().mtu(1480, Some(addr("2001:db8:ff::1"))),
+        )],
+    );
+    let mut pinger = client.pinger(addr("2001:db8::1")).await;
+
+    let path = pinger.discover_mtu(MtuPlan::new().max(9000)).await.unwrap();
+    assert_eq!(path.mtu, 1480);
+    assert_eq!(path.reported_by, Some(addr("2001:db8:ff::1")));
+
+    pinger.size(1500 - 48);
+    match pinger.ping(0).await {
+        Err(SurgeError::IcmpError { error, .. }) => {
+            assert_eq!(error, IcmpError::PacketTooBig { mtu: 1480 })
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn black_hole() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().mtu(1006, None))]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(100));
+
+    let path = pinger.discover_mtu(MtuPlan::new().probes(1)).await.unwrap();
+    assert_eq!(path.mtu, 1006);
+    assert_eq!(path.reported_by, None);
+
+    // nothing gets through at all
+    let result = pinger.discover_mtu(MtuPlan::new().min(1200)).await;
+    assert!(matches!(result, Err(SurgeError::Timeout { .. })));
+}
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{Client, Config, RateLimit, RateLimitStats, ICMP};
+
+mod common;
+
+use common::addr;
+
+fn network(hosts: &[&str]) -> MockNetwork {
+    let network = MockNetwork::new();
+    for host in hosts {
+        network.host(addr(host), MockHost::new()).unwrap();
+    }
+    network
+}
+
+#[tokio::test]
+async fn global_limit_is_shared_by_pingers() {
+    let network = network(&["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+    let config = Config::builder()
+        .rate_limit(RateLimit::new(100.0, 5))
+        .build();
+    let client = Client::with_transport_config(network.transport(ICMP::V4), &config);
+    let mut pingers = Vec::new();
+    for host in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+        pingers.push(client.pinger(addr(host)).await);
+    }
+
+    let start = Instant::now();
+    let pings = pingers
+        .iter()
+        .flat_map(|pinger| (0..5).map(move |seq| pinger.ping(seq)));
+    for result in join_all(pings).await {
+        let (_, rtt) = result.unwrap();
+        // waiting for a token is not part of the round trip
+        assert!(rtt < Duration::from_millis(50), "{:?}", rtt);
+    }
+    // a burst of 5, then the other 10 every 10ms
+    assert!(start.elapsed() >= Duration::from_millis(90));
+
+    let stats = client.rate_limit_stats();
+    assert_eq!(stats.requests, 15);
+    assert_eq!(stats.delayed, 10);
+    assert!(stats.max_wait >= Duration::from_millis(90));
+    assert!(stats.total_wait >= stats.max_wait);
+}
+
+#[tokio::test]
+async fn destination_limit_is_per_destination() {
+    let network = network(&["10.0.0.1", "10.0.0.2"]);
+    let config = Config::builder()
+        .destination_rate_limit(RateLimit::new(20.0, 1))
+        .build();
+    let client = Client::with_transport_config(network.transport(ICMP::V4), &config);
+    let pingers = [
+        client.pinger(addr("10.0.0.1")).await,
+        client.pinger(addr("10.0.0.2")).await,
+    ];
+
+    let start = Instant::now();
+    let pings = pingers
+        .iter()
+        .flat_map(|pinger| (0..3).map(move |seq| pinger.ping(seq)));
+    for result in join_all(pings).await {
+        result.unwrap();
+    }
+    // 50ms between the requests of each destination, side by side
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(90), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(250), "{:?}", elapsed);
+    assert_eq!(client.rate_limit_stats().delayed, 4);
+}
+
+#[tokio::test]
+async fn cancelled_requests_give_their_token_back() {
+    let limit = RateLimit::new(10.0, 1);
+    for config in [
+        Config::builder().rate_limit(limit).build(),
+        Config::builder().destination_rate_limit(limit).build(),
+    ] {
+        let network = network(&["10.0.0.1"]);
+        let client = Client::with_transport_config(network.transport(ICMP::V4), &config);
+        let pinger = client.pinger(addr("10.0.0.1")).await;
+
+        let start = Instant::now();
+        pinger.ping(0).await.unwrap();
+        // gives up while waiting for the token of 100ms
+        let cancelled = tokio::time::timeout(Duration::from_millis(20), pinger.ping(1)).await;
+        assert!(cancelled.is_err());
+        tokio::time::sleep_until((start + Duration::from_millis(100)).into()).await;
+
+        let waited = Instant::now();
+        pinger.ping(2).await.unwrap();
+        assert!(waited.elapsed() < Duration::from_millis(60));
+    }
+}
+
+#[tokio::test]
+async fn unlimited_by_default() {
+    let network = network(&["10.0.0.1"]);
+    let client = Client::with_transport(network.transport(ICMP::V4));
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    for seq in 0..10 {
+        pinger.ping(seq).await.unwrap();
+    }
+    assert_eq!(client.rate_limit_stats(), RateLimitStats::default());
+}
+
+#[tokio::test]
+async fn extreme_rates_are_clamped() {
+    assert_eq!(RateLimit::new(1e-30, 1).rate(), 1.0 / 86_400.0);
+    assert_eq!(RateLimit::new(f64::INFINITY, 1).rate(), 1e9);
+    assert_eq!(RateLimit::new(f64::NAN, 0), RateLimit::new(1.0, 1));
+
+    let network = network(&["10.0.0.1"]);
+    let config = Config::builder()
+        .rate_limit(RateLimit::new(1e-30, u32::MAX))
+        .build();
+    let client = Client::with_transport_config(network.transport(ICMP::V4), &config);
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+    for seq in 0..3 {
+        pinger.ping(seq).await.unwrap();
+    }
+    assert_eq!(client.rate_limit_stats().delayed, 0);
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{Client, FamilyPreference, ResolvePlan, SurgeError, ICMP};
+
+mod common;
+
+use common::{addr, dual_stack};
+
+#[tokio::test]
+async fn address_literal() {
+    let network = MockNetwork::new();
+    network.host(addr("2001:db8::1"), MockHost::new()).unwrap();
+    let client = dual_stack(&network);
+
+    let mut pinger = client
+        .pinger_for_host("2001:db8::1", ResolvePlan::new())
+        .await
+        .unwrap();
+    assert_eq!(pinger.host(), "2001:db8::1");
+    let (from, outcome) = pinger.ping(0).await;
+    assert_eq!(from, addr("2001:db8::1"));
+    assert!(outcome.is_ok());
+    assert!(!pinger.refresh().await.unwrap());
+}
+
+#[tokio::test]
+async fn family_preference() {
+    let network = MockNetwork::new();
+    network.host(addr("127.0.0.1"), MockHost::new()).unwrap();
+    let client = dual_stack(&network);
+
+    let mut pinger = client
+        .pinger_for_host(
+            "localhost",
+            ResolvePlan::new().family(FamilyPreference::V4Only),
+        )
+        .await
+        .unwrap();
+    assert_eq!(pinger.address(), addr("127.0.0.1"));
+    assert!(pinger.ping(0).await.1.is_ok());
+
+    let err = client
+        .pinger_for_host(
+            "192.0.2.1",
+            ResolvePlan::new().family(FamilyPreference::V6Only),
+        )
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "no IPv6 address for 192.0.2.1");
+}
+
+#[tokio::test]
+async fn refresh_follows_the_name() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    network.host(addr("2001:db8::1"), MockHost::new()).unwrap();
+    network.name("svc.test", vec![addr("10.0.0.1")]);
+    let client = dual_stack(&network);
+
+    let plan = ResolvePlan::new()
+        .refresh(Duration::from_millis(20))
+        .resolver(network.resolver());
+    let mut pinger = client.pinger_for_host("svc.test", plan).await.unwrap();
+    pinger.timeout(Duration::from_millis(50));
+    let (from, outcome) = pinger.ping(0).await;
+    assert_eq!(from, addr("10.0.0.1"));
+    assert!(outcome.is_ok());
+
+    // not due yet
+    network.name("svc.test", vec![addr("2001:db8::1")]);
+    assert_eq!(pinger.ping(1).await.0, addr("10.0.0.1"));
+
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    let (from, outcome) = pinger.ping(2).await;
+    assert_eq!(from, addr("2001:db8::1"));
+    assert!(outcome.is_ok());
+    assert!(!pinger.refresh().await.unwrap());
+
+    // the settings carry over to the new address
+    network
+        .host(
+            addr("2001:db8::1"),
+            MockHost::new().delay(Duration::from_millis(100)),
+        )
+        .unwrap();
+    assert!(matches!(
+        pinger.ping(3).await.1,
+        Err(SurgeError::Timeout { .. })
+    ));
+
+    // failing to resolve keeps the address
+    network.remove_name("svc.test");
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(pinger.ping(4).await.0, addr("2001:db8::1"));
+    assert!(pinger.refresh().await.is_err());
+}
+
+#[tokio::test]
+async fn single_family_client() {
+    let network = MockNetwork::new();
+    network.host(addr("2001:db8::1"), MockHost::new()).unwrap();
+    network.name("svc.test", vec![addr("10.0.0.1"), addr("2001:db8::1")]);
+    let client = Client::with_transport(network.transport(ICMP::V6));
+
+    let plan = ResolvePlan::new()
+        .family(FamilyPreference::V6Only)
+        .resolver(network.resolver());
+    let mut pinger = client.pinger_for_host("svc.test", plan).await.unwrap();
+    let (from, outcome) = pinger.ping(0).await;
+    assert_eq!(from, addr("2001:db8::1"));
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn single_family_client_picks_its_family() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    network.host(addr("2001:db8::1"), MockHost::new()).unwrap();
+    network.name("svc.test", vec![addr("2001:db8::1"), addr("10.0.0.1")]);
+    let client = Client::with_transport(network.transport(ICMP::V4));
+
+    for family in [
+        FamilyPreference::Any,
+        FamilyPreference::PreferV6,
+        FamilyPreference::V4Only,
+    ] {
+        let plan = ResolvePlan::new()
+            .family(family)
+            .resolver(network.resolver());
+        let mut pinger = client.pinger_for_host("svc.test", plan).await.unwrap();
+        let (from, outcome) = pinger.ping(0).await;
+        assert_eq!(from, addr("10.0.0.1"));
+        assert!(outcome.is_ok());
+    }
+
+    let plan = ResolvePlan::new()
+        .family(FamilyPreference::V6Only)
+        .resolver(network.resolver());
+    let err = client
+        .pinger_for_host("svc.test", plan)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    network.name("v6.test", vec![addr("2001:db8::1")]);
+    let plan = ResolvePlan::new().resolver(network.resolver());
+    let err = client.pinger_for_host("v6.test", plan).await.err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
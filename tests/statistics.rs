@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use surge_ping::mock::MockHost;
+use surge_ping::{PingStatistics, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[tokio::test]
+async fn counts_replies_and_losses() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [
+            ("10.0.0.1", MockHost::new()),
+            ("10.0.0.2", MockHost::new().loss(1.0)),
+        ],
+    );
+
+    let mut stats = PingStatistics::new();
+    for (host, count) in &[("10.0.0.1", 3), ("10.0.0.2", 1)] {
+        let mut pinger = client.pinger(addr(host)).await;
+        pinger.timeout(Duration::from_millis(50));
+        for seq in 0..*count {
+            stats.record(&pinger.ping(seq).await);
+        }
+    }
+
+    assert_eq!(stats.transmitted(), 4);
+    assert_eq!(stats.received(), 3);
+    assert_eq!(stats.loss(), 25.0);
+    assert!(stats.min().unwrap() <= stats.avg().unwrap());
+    assert!(stats.avg().unwrap() <= stats.max().unwrap());
+    assert!(stats.mdev().is_some());
+
+    let summary = stats.to_string();
+    assert!(summary.starts_with("4 packets transmitted, 3 received, 25% packet loss, time "));
+    assert!(summary.contains("\nrtt min/avg/max/mdev = "));
+}
+
+#[tokio::test]
+async fn merge_and_reset() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let mut first = PingStatistics::new();
+    let mut second = PingStatistics::new();
+    first.record(&pinger.ping(0).await);
+    second.record(&pinger.ping(1).await);
+    second.record_duplicate();
+
+    first.merge(&second);
+    assert_eq!(first.transmitted(), 2);
+    assert_eq!(first.received(), 2);
+    assert_eq!(first.duplicates(), 1);
+    assert!(first
+        .to_string()
+        .contains(", +1 duplicates, 0% packet loss"));
+
+    let window = first.take();
+    assert_eq!(window.transmitted(), 2);
+    assert_eq!(first.transmitted(), 0);
+    assert_eq!(first.avg(), None);
+    assert_eq!(
+        first.to_string(),
+        "0 packets transmitted, 0 received, 0% packet loss, time 0ms"
+    );
+}
@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use surge_ping::mock::MockHost;
+use surge_ping::{PingEvent, PingPlan, PingStatistics, SurgeError, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[tokio::test]
+async fn count_ends_the_stream() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let plan = PingPlan::new()
+        .interval(Duration::from_millis(10))
+        .count(5)
+        .start_sequence(u16::MAX - 1);
+    let seqs: Vec<u16> = pinger
+        .stream(plan)
+        .map(|(seq, outcome)| {
+            assert!(outcome.is_ok());
+            seq
+        })
+        .collect()
+        .await;
+    assert_eq!(seqs, vec![u16::MAX - 1, u16::MAX, 0, 1, 2]);
+}
+
+#[tokio::test]
+async fn slow_replies_do_not_delay_sending() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new().delay(Duration::from_millis(300)),
+        )],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let start = Instant::now();
+    let plan = PingPlan::new()
+        .interval(Duration::from_millis(20))
+        .count(10);
+    let outcomes: Vec<_> = pinger.stream(plan).collect().await;
+    assert_eq!(outcomes.len(), 10);
+    assert!(outcomes.iter().all(|(_, outcome)| outcome.is_ok()));
+    assert!(start.elapsed() < Duration::from_millis(800));
+}
+
+#[tokio::test]
+async fn deadline_stops_an_endless_stream() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().loss(1.0))]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(50));
+
+    let plan = PingPlan::new()
+        .interval(Duration::from_millis(20))
+        .deadline(Duration::from_millis(300));
+    let outcomes: Vec<_> = pinger.stream(plan).collect().await;
+    assert!(!outcomes.is_empty());
+    assert!(outcomes
+        .iter()
+        .all(|(seq, outcome)| matches!(outcome, Err(SurgeError::Timeout { seq: s }) if s == seq)));
+}
+
+#[tokio::test]
+async fn events_report_duplicates() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().duplicates(1))]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(200));
+
+    let plan = PingPlan::new().interval(Duration::from_millis(50)).count(2);
+    let mut stats = PingStatistics::new();
+    let mut events = Vec::new();
+    let mut stream = pinger.events(plan);
+    while let Some(event) = stream.next().await {
+        stats.record_event(&event);
+        events.push(event);
+    }
+    assert!(matches!(
+        events[..3],
+        [
+            PingEvent::Outcome {
+                seq: 0,
+                result: Ok(_),
+                reordered: false
+            },
+            PingEvent::Duplicate { seq: 0, .. },
+            PingEvent::Outcome { seq: 1, .. },
+        ]
+    ));
+    assert_eq!(stats.transmitted(), 2);
+    assert_eq!(stats.received(), 2);
+    assert!(stats.duplicates() >= 1);
+}
+
+#[tokio::test]
+async fn events_report_late_replies() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().delay(Duration::from_millis(80)))],
+    );
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(30));
+
+    let plan = PingPlan::new()
+        .interval(Duration::from_millis(200))
+        .count(2);
+    let events: Vec<_> = pinger.events(plan).collect().await;
+    let late = events
+        .iter()
+        .filter_map(|event| match event {
+            PingEvent::Late { seq, rtt, late_by } => Some((*seq, *rtt, *late_by)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // the reply to the last request comes after the stream stopped waiting for it
+    assert_eq!(late.len(), 1);
+    let (seq, rtt, late_by) = late[0];
+    assert_eq!(seq, 0);
+    assert!(rtt >= Duration::from_millis(80));
+    assert!(late_by >= Duration::from_millis(50) && late_by < rtt);
+    assert!(matches!(
+        events[0],
+        PingEvent::Outcome {
+            seq: 0,
+            result: Err(SurgeError::Timeout { .. }),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn events_report_late_replies_to_the_last_request() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [("10.0.0.1", MockHost::new().delay(Duration::from_millis(50)))],
+    );
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(30));
+
+    let events: Vec<_> = pinger.events(PingPlan::new().count(1)).collect().await;
+    assert!(matches!(
+        events[..],
+        [
+            PingEvent::Outcome {
+                seq: 0,
+                result: Err(SurgeError::Timeout { .. }),
+                ..
+            },
+            PingEvent::Late { seq: 0, .. },
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn events_report_reordering() {
+    let (network, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new().delay(Duration::from_millis(100)),
+        )],
+    );
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(200));
+
+    let faster = network.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        faster.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    });
+    let plan = PingPlan::new().interval(Duration::from_millis(40)).count(2);
+    let mut stats = PingStatistics::new();
+    let mut outcomes = Vec::new();
+    let mut stream = pinger.events(plan);
+    while let Some(event) = stream.next().await {
+        stats.record_event(&event);
+        if let PingEvent::Outcome { seq, reordered, .. } = event {
+            outcomes.push((seq, reordered));
+        }
+    }
+    assert_eq!(outcomes, vec![(1, false), (0, true)]);
+    assert_eq!(stats.reordered(), 1);
+}
+
+#[tokio::test]
+async fn zero_interval_is_clamped() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new())]);
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let plan = PingPlan::new().interval(Duration::ZERO).count(3);
+    let outcomes: Vec<_> = pinger.stream(plan).collect().await;
+    assert_eq!(outcomes.len(), 3);
+    assert!(outcomes.iter().all(|(_, outcome)| outcome.is_ok()));
+}
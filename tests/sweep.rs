@@ -0,0 +1,168 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{Client, HostStatus, IcmpError, IpRange, SweepPlan, Unreachable, ICMP};
+
+mod common;
+
+use common::{addr, dual_stack, mock_client};
+
+fn addrs(range: &str) -> Vec<IpAddr> {
+    range.parse::<IpRange>().unwrap().iter().collect()
+}
+
+#[test]
+fn parse_ranges() {
+    assert_eq!(
+        addrs("10.0.0.0/30"),
+        vec![addr("10.0.0.1"), addr("10.0.0.2")]
+    );
+    assert_eq!(
+        addrs("10.0.0.6/31"),
+        vec![addr("10.0.0.6"), addr("10.0.0.7")]
+    );
+    assert_eq!(addrs("10.0.0.9/32"), vec![addr("10.0.0.9")]);
+    assert_eq!("10.0.0.0/22".parse::<IpRange>().unwrap().len(), 1022);
+    assert_eq!(
+        addrs("2001:db8::/126"),
+        vec![
+            addr("2001:db8::"),
+            addr("2001:db8::1"),
+            addr("2001:db8::2"),
+            addr("2001:db8::3")
+        ]
+    );
+    assert_eq!(
+        addrs("10.0.0.254 - 10.0.1.1"),
+        vec![
+            addr("10.0.0.254"),
+            addr("10.0.0.255"),
+            addr("10.0.1.0"),
+            addr("10.0.1.1")
+        ]
+    );
+    assert_eq!(addrs("::1"), vec![addr("::1")]);
+
+    for invalid in [
+        "10.0.0.0/33",
+        "10.0.0.2-10.0.0.1",
+        "10.0.0.1-::2",
+        "10.0.0/24",
+        "",
+    ] {
+        assert!(invalid.parse::<IpRange>().is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn alive_dead_and_unreachable() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [
+            ("10.0.0.1", MockHost::new()),
+            ("10.0.0.2", MockHost::new().error(addr("10.0.0.254"), 3, 1)),
+            ("10.0.0.5", MockHost::new()),
+        ],
+    );
+
+    let plan = SweepPlan::new().timeout(Duration::from_millis(50));
+    let report = client.sweep(&["10.0.0.0/29".parse().unwrap()], plan).await;
+
+    let hosts: Vec<IpAddr> = report.hosts.iter().map(|host| host.addr).collect();
+    assert_eq!(hosts, addrs("10.0.0.0/29"));
+    assert_eq!(
+        report.alive().collect::<Vec<_>>(),
+        vec![addr("10.0.0.1"), addr("10.0.0.5")]
+    );
+    assert_eq!(
+        report.dead().collect::<Vec<_>>(),
+        vec![addr("10.0.0.3"), addr("10.0.0.4"), addr("10.0.0.6")]
+    );
+    assert_eq!(
+        report.unreachable().collect::<Vec<_>>(),
+        vec![(
+            addr("10.0.0.2"),
+            addr("10.0.0.254"),
+            IcmpError::DestinationUnreachable(Unreachable::Host)
+        )]
+    );
+    assert!(matches!(
+        report.hosts[0].status,
+        HostStatus::Alive { attempts: 1, .. }
+    ));
+}
+
+#[tokio::test]
+async fn retries_dead_hosts() {
+    let network = MockNetwork::new();
+    let client = Client::with_transport(network.transport(ICMP::V4));
+
+    let plan = SweepPlan::new()
+        .timeout(Duration::from_millis(50))
+        .retries(2);
+    let start = Instant::now();
+    let report = client.sweep(&[addr("10.0.0.1").into()], plan).await;
+    assert_eq!(report.dead().count(), 1);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+}
+
+#[tokio::test]
+async fn rate_limits_requests() {
+    let network = MockNetwork::new();
+    for host in addrs("10.0.0.0/29") {
+        network.host(host, MockHost::new()).unwrap();
+    }
+    let client = Client::with_transport(network.transport(ICMP::V4));
+
+    let start = Instant::now();
+    let report = client
+        .sweep(
+            &["10.0.0.0/29".parse().unwrap()],
+            SweepPlan::new().rate(50.0),
+        )
+        .await;
+    assert_eq!(report.alive().count(), 6);
+    // the first request goes out at once, the other five 20ms apart
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn dual_stack_sweep() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    network.host(addr("2001:db8::1"), MockHost::new()).unwrap();
+    let client = dual_stack(&network);
+
+    let ranges = [
+        "10.0.0.1".parse().unwrap(),
+        "2001:db8::/127".parse().unwrap(),
+    ];
+    let plan = SweepPlan::new().timeout(Duration::from_millis(50));
+    let report = client.sweep(&ranges, plan).await;
+    assert_eq!(
+        report.alive().collect::<Vec<_>>(),
+        vec![addr("10.0.0.1"), addr("2001:db8::1")]
+    );
+    assert_eq!(report.dead().collect::<Vec<_>>(), vec![addr("2001:db8::")]);
+}
+
+#[tokio::test]
+async fn large_ranges_are_capped() {
+    let (_, client) = mock_client(ICMP::V6, [("2001:db8::1", MockHost::new())]);
+
+    let plan = SweepPlan::new()
+        .timeout(Duration::from_millis(20))
+        .retries(0)
+        .rate(f64::INFINITY)
+        .max_hosts(4);
+    let report = client
+        .sweep(&["2001:db8::/64".parse().unwrap()], plan)
+        .await;
+    assert_eq!(report.hosts.len(), 4);
+    assert_eq!(report.skipped, (1u128 << 64) - 4);
+    assert_eq!(
+        report.alive().collect::<Vec<_>>(),
+        vec![addr("2001:db8::1")]
+    );
+}
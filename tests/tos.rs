@@ -0,0 +1,62 @@
+use socket2::Type;
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{Client, Config, SendOptions, ICMP};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[tokio::test]
+async fn replies_carry_the_tos() {
+    let network = MockNetwork::new();
+    network.host(addr("10.0.0.1"), MockHost::new()).unwrap();
+    network.host(addr("2001:db8::1"), MockHost::new()).unwrap();
+
+    for (kind, sock_type, destination) in [
+        (ICMP::V4, Type::RAW, "10.0.0.1"),
+        (ICMP::V4, Type::DGRAM, "10.0.0.1"),
+        (ICMP::V6, Type::RAW, "2001:db8::1"),
+    ] {
+        let client = Client::with_transport(network.transport_with_type(kind, sock_type));
+        let mut pinger = client.pinger(addr(destination)).await;
+
+        let (reply, _) = pinger.ping(0).await.unwrap();
+        assert_eq!(reply.get_tos(), Some(0));
+
+        pinger.tos((46 << 2) | 0b01);
+        let (reply, _) = pinger.ping(1).await.unwrap();
+        assert_eq!(reply.get_tos(), Some((46 << 2) | 0b01));
+        assert_eq!(reply.get_dscp(), Some(46));
+        assert_eq!(reply.get_ecn(), Some(0b01));
+
+        // per request options win over the pinger
+        let mut options = SendOptions::new();
+        options.tos = Some(10 << 2);
+        let (reply, _) = pinger.ping_with(2, options).await.unwrap();
+        assert_eq!(reply.get_dscp(), Some(10));
+    }
+}
+
+#[tokio::test]
+async fn remarking_is_visible() {
+    let (_, client) = mock_client(ICMP::V4, [("10.0.0.1", MockHost::new().remark(0))]);
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.tos(46 << 2);
+
+    let (reply, _) = pinger.ping(0).await.unwrap();
+    assert_eq!(reply.get_dscp(), Some(0));
+}
+
+#[test]
+fn dscp_and_ecn_are_clamped() {
+    let config = Config::builder().dscp(46).ecn(0b01).build();
+    assert_eq!(config.tos, Some((46 << 2) | 0b01));
+
+    // neither spills into the other
+    let config = Config::builder().ecn(0b10).dscp(64).build();
+    assert_eq!(config.tos, Some((63 << 2) | 0b10));
+    let config = Config::builder().dscp(10).ecn(4).build();
+    assert_eq!(config.tos, Some((10 << 2) | 0b11));
+    let config = Config::builder().tos(0xff).dscp(0).build();
+    assert_eq!(config.tos, Some(0b11));
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use socket2::Type;
+use surge_ping::mock::{MockHost, MockNetwork};
+use surge_ping::{
+    Client, IcmpError, Probe, TimeExceeded, TracePlan, TraceStatus, Unreachable, ICMP,
+};
+
+mod common;
+
+use common::{addr, mock_client};
+
+#[tokio::test]
+async fn hops_v4() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new().path(vec![addr("192.0.2.1"), addr("192.0.2.2")]),
+        )],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let trace = pinger.traceroute(TracePlan::new()).await.unwrap();
+    assert!(trace.reached());
+    assert_eq!(trace.hops.len(), 3);
+    assert_eq!(trace.hops[0].responders(), vec![addr("192.0.2.1")]);
+    assert_eq!(trace.hops[1].responders(), vec![addr("192.0.2.2")]);
+    assert_eq!(trace.hops[2].responders(), vec![addr("10.0.0.1")]);
+    for probe in &trace.hops[0].probes {
+        assert!(matches!(
+            probe,
+            Probe::Error {
+                error: IcmpError::TimeExceeded(TimeExceeded::Transit),
+                ..
+            }
+        ));
+    }
+    assert!(trace.hops[2]
+        .probes
+        .iter()
+        .all(|probe| matches!(probe, Probe::Reply { .. })));
+}
+
+#[tokio::test]
+async fn hops_v6() {
+    let (_, client) = mock_client(
+        ICMP::V6,
+        [(
+            "2001:db8::1",
+            MockHost::new().path(vec![addr("2001:db8:ff::1")]),
+        )],
+    );
+    let pinger = client.pinger(addr("2001:db8::1")).await;
+
+    let trace = pinger.traceroute(TracePlan::new().probes(1)).await.unwrap();
+    assert_eq!(trace.status, TraceStatus::Reached);
+    assert_eq!(trace.hops[0].ttl, 1);
+    assert_eq!(trace.hops[0].responders(), vec![addr("2001:db8:ff::1")]);
+    assert_eq!(trace.hops[1].responders(), vec![addr("2001:db8::1")]);
+}
+
+#[tokio::test]
+async fn unreachable_ends_trace() {
+    let (_, client) = mock_client(
+        ICMP::V4,
+        [(
+            "10.0.0.1",
+            MockHost::new()
+                .path(vec![addr("192.0.2.1")])
+                .error(addr("192.0.2.9"), 3, 1),
+        )],
+    );
+    let pinger = client.pinger(addr("10.0.0.1")).await;
+
+    let trace = pinger.traceroute(TracePlan::new()).await.unwrap();
+    assert_eq!(trace.hops.len(), 2);
+    assert_eq!(
+        trace.status,
+        TraceStatus::Unreachable {
+            from: addr("192.0.2.9"),
+            error: IcmpError::DestinationUnreachable(Unreachable::Host),
+        }
+    );
+    assert!(trace.hops[1].to_string().ends_with("!H"));
+}
+
+#[tokio::test]
+async fn max_hops() {
+    let network = MockNetwork::new();
+    network
+        .host(
+            addr("10.0.0.1"),
+            MockHost::new().path(vec![addr("192.0.2.1"); 10]),
+        )
+        .unwrap();
+    let client = Client::with_transport(network.transport(ICMP::V4));
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(100));
+
+    let trace = pinger
+        .traceroute(TracePlan::new().first_hop(3).max_hops(5).probes(2))
+        .await
+        .unwrap();
+    assert_eq!(trace.status, TraceStatus::MaxHops);
+    let ttls: Vec<u8> = trace.hops.iter().map(|hop| hop.ttl).collect();
+    assert_eq!(ttls, vec![3, 4, 5]);
+
+    // a maximum below the first hop still probes the first hop
+    for plan in [
+        TracePlan::new().first_hop(7).max_hops(5),
+        TracePlan::new().max_hops(7).first_hop(7),
+        TracePlan::new().max_hops(0).first_hop(7),
+    ] {
+        let trace = pinger.traceroute(plan.probes(1)).await.unwrap();
+        assert_eq!(trace.status, TraceStatus::MaxHops);
+        let ttls: Vec<u8> = trace.hops.iter().map(|hop| hop.ttl).collect();
+        assert_eq!(ttls, vec![7]);
+    }
+    let trace = pinger
+        .traceroute(TracePlan::new().max_hops(0).probes(1))
+        .await
+        .unwrap();
+    let ttls: Vec<u8> = trace.hops.iter().map(|hop| hop.ttl).collect();
+    assert_eq!(ttls, vec![1]);
+}
+
+#[tokio::test]
+async fn corrupt_replies_are_failed_probes() {
+    let network = MockNetwork::new();
+    network
+        .host(
+            addr("10.0.0.1"),
+            MockHost::new()
+                .path(vec![addr("192.0.2.1")])
+                .corrupt(vec![0]),
+        )
+        .unwrap();
+    let client = Client::with_transport(network.transport(ICMP::V4));
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.verify_payload(true);
+
+    let trace = pinger
+        .traceroute(TracePlan::new().max_hops(3).probes(2))
+        .await
+        .unwrap();
+    assert_eq!(trace.status, TraceStatus::MaxHops);
+    assert_eq!(trace.hops.len(), 3);
+    assert_eq!(trace.hops[0].responders(), vec![addr("192.0.2.1")]);
+    for hop in &trace.hops[1..] {
+        for probe in &hop.probes {
+            assert!(
+                matches!(probe, Probe::Failed { rtt: Some(_) }),
+                "{:?}",
+                probe
+            );
+        }
+        assert!(hop.to_string().ends_with("ms !?"), "{}", hop);
+    }
+}
+
+#[tokio::test]
+async fn dgram_hops_time_out() {
+    let network = MockNetwork::new();
+    network
+        .host(
+            addr("10.0.0.1"),
+            MockHost::new().path(vec![addr("192.0.2.1")]),
+        )
+        .unwrap();
+    let client = Client::with_transport(network.transport_with_type(ICMP::V4, Type::DGRAM));
+    let mut pinger = client.pinger(addr("10.0.0.1")).await;
+    pinger.timeout(Duration::from_millis(50));
+
+    // ping sockets never see the Time Exceeded of the router
+    let trace = pinger.traceroute(TracePlan::new().probes(1)).await.unwrap();
+    assert!(trace.reached());
+    assert!(matches!(trace.hops[0].probes[..], [Probe::Timeout]));
+    assert_eq!(trace.hops[1].responders(), vec![addr("10.0.0.1")]);
+}